@@ -55,6 +55,9 @@ fn build() {
     if is_release {
         build.define("NDEBUG", None);
     }
+    // edflib.c maps fopeno/fseeko/ftello to their 64-bit variants, which are only declared with these
+    build.define("_LARGEFILE64_SOURCE", None);
+    build.define("_LARGEFILE_SOURCE", None);
     build.warnings(false);
     build.compile(EDFLIB);
 }
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use edflib::{EDFChannel, EDFHeader, EDFPatientInfo, EDFWriter};
+use edflib::{EDFChannel, EDFDate, EDFDateTime, EDFHeader, EDFPatientInfo, EDFWriterBuilder};
 use std::f64::consts::PI;
 pub fn main() -> Result<()> {
     // 1. 构建通道信息
@@ -12,7 +12,8 @@ pub fn main() -> Result<()> {
         physical_max: 2000.0,
         physical_min: -2000.0,
         physical_dimension: "mV".to_string(),
-        sample_frequency: sample_rate,
+        prefilter: "HP:0.1Hz LP:75Hz".to_string(),
+        sample_frequency: sample_rate as f64,
     };
     let channel_1 = EDFChannel {
         label: "Sine50Hz".to_string(),
@@ -22,7 +23,8 @@ pub fn main() -> Result<()> {
         physical_max: 2000.0,
         physical_min: -2000.0,
         physical_dimension: "mV".to_string(),
-        sample_frequency: sample_rate,
+        prefilter: "HP:0.1Hz LP:75Hz".to_string(),
+        sample_frequency: sample_rate as f64,
     };
 
     // 2. 构建患者及头信息
@@ -30,23 +32,28 @@ pub fn main() -> Result<()> {
         patient_name: "Demo".to_string(),
         patient_code: "0001".to_string(),
         sex: 0, // 0:female, 1:male
+        birthdate: Some(EDFDate::new(1990, 5, 17)),
+        patient_additional: String::new(),
         admin_code: "0001".to_string(),
         technician: "DYZS".to_string(),
         equipment: "DYZS".to_string(),
+        recording_additional: String::new(),
     };
 
     let header = EDFHeader {
         patient_info,
         channels: vec![channel_0, channel_1],
+        start_datetime: Some(EDFDateTime::new(EDFDate::new(2024, 12, 27), 10, 0, 0)),
+        datarecord_duration: None,
+        annotation_channels: None,
+        annotation_position: None,
+        file_format: None,
     };
 
-    // 3. 构建 EDFWriter
-    let mut writer = EDFWriter::new("generator.bdf".into(), header);
+    // 3. 打开文件进行写入
+    let mut writer = EDFWriterBuilder::from_header("generator.bdf".into(), header).open()?;
 
-    // 4. 打开文件进行写入
-    writer.open()?;
-
-    // 5. 构造多帧数据：
+    // 4. 构造多帧数据：
     //    frames_data[帧索引][通道索引] = Vec<f64>（该帧的采样点）
     let duration_in_seconds = 10; // 10 秒
     let mut frames_data: Vec<Vec<Vec<f64>>> = Vec::with_capacity(duration_in_seconds);
@@ -69,18 +76,18 @@ pub fn main() -> Result<()> {
         frames_data.push(vec![ch0_data, ch1_data]);
     }
 
-    // 6. 使用 "按帧写入" 的方式，一次性写入所有帧
-    writer.write_multi_frames(&mut frames_data)?;
+    // 5. 使用 "按帧写入" 的方式，一次性写入所有帧
+    writer.write_multi_frames(&frames_data)?;
 
-    // 7. 写注释
-    writer.write_annotation(0, 0, "Start of recording".to_string())?;
+    // 6. 写注释
+    writer.write_annotation(0, Some(0), "Start of recording")?;
     writer.write_annotation(
         (duration_in_seconds * 1_000_000) as i64,
-        0,
-        "End of recording".to_string(),
+        Some(0),
+        "End of recording",
     )?;
 
-    // 8. 完成写入并关闭文件
+    // 7. 完成写入并关闭文件
     writer.finish()?;
 
     println!("BDF 文件生成成功: generator.bdf");
@@ -0,0 +1,231 @@
+use crate::base::*;
+use crate::error::*;
+#[cfg(feature = "pure-rust")]
+use crate::native::*;
+use crate::reader::*;
+use crate::utils::*;
+use crate::writer::*;
+#[cfg(feature = "pure-rust")]
+use std::any::Any;
+use std::{io::SeekFrom, path::Path, time::Duration};
+
+/// Datarecords shorter than this are set with a resolution of 1 µs instead of 10 µs
+pub(crate) const MICRO_DATARECORD_LIMIT: Duration = Duration::from_millis(10);
+
+/// The implementation that writes the file of an [`OpenEDFWriter`], see
+/// [`EDFWriterBuilder::backend`](crate::EDFWriterBuilder::backend).
+///
+/// Both backends write the same bytes for the same header, samples and annotations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// The bundled C library edflib
+    #[default]
+    Edflib,
+    /// A writer in Rust that does not call into edflib, only available with the `pure-rust` feature
+    #[cfg(feature = "pure-rust")]
+    PureRust,
+}
+
+impl Backend {
+    /// Creates the file and writes the header settings.
+    ///
+    /// Plain EDF and BDF files are always written by the pure-Rust backend, edflib cannot write them.
+    pub(crate) fn create(self, settings: &WriterSettings) -> Result<Box<dyn WriterBackend>> {
+        #[cfg(feature = "pure-rust")]
+        if !settings.format.is_plus() {
+            return Ok(Box::new(NativeWriter::create(settings)?));
+        }
+        match self {
+            Backend::Edflib => Ok(Box::new(create_edflib(settings)?)),
+            #[cfg(feature = "pure-rust")]
+            Backend::PureRust => Ok(Box::new(NativeWriter::create(settings)?)),
+        }
+    }
+}
+
+/// The header settings checked by `OpenEDFWriter`, from which a backend creates the file
+pub(crate) struct WriterSettings<'a> {
+    pub file_path: &'a Path,
+    pub header: &'a EDFHeader,
+    pub format: FileFormat,
+    pub datarecord_duration: Duration,
+    /// Number of samples of every channel in a datarecord
+    pub samples_in_datarecord: &'a [usize],
+    pub overlong_text: OverlongText,
+}
+
+/// Writes the datarecords and annotations of a file created by [`Backend::create`].
+///
+/// The samples of a datarecord are channel-major, `samples_per_record` is the number of samples
+/// of all channels in one datarecord.
+pub(crate) trait WriterBackend: Send {
+    /// Sets the number of annotation channels, only before the first datarecord.
+    fn set_number_of_annotation_signals(&mut self, annot_signals: usize) -> Result<()>;
+
+    /// Writes whole datarecords of physical samples.
+    fn write_physical_records(&mut self, samples: &[f64], samples_per_record: usize) -> Result<()>;
+
+    /// Writes whole datarecords of digital samples, clamping them to the digital range.
+    fn write_digital_records(&mut self, samples: &[i32], samples_per_record: usize) -> Result<()>;
+
+    /// Stores an annotation, `onset` and `duration` in microseconds, -1 for an unknown duration.
+    fn write_annotation(&mut self, onset: i64, duration: i64, description: &str) -> Result<()>;
+
+    /// Writes the annotations and the number of datarecords and closes the file.
+    fn finish(&mut self) -> Result<()>;
+
+    /// Returns the sink the file was written to once it is closed, `None` for edflib.
+    #[cfg(feature = "pure-rust")]
+    fn into_sink(self: Box<Self>) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+}
+
+/// Creates the backend of an `OpenEDFWriter` that writes to a sink instead of `file_path`
+#[cfg(feature = "pure-rust")]
+pub(crate) type CreateSink =
+    Box<dyn FnOnce(&WriterSettings) -> Result<Box<dyn WriterBackend>> + Send>;
+
+/// The sink of an `OpenEDFWriter` opened by `EDFWriterBuilder::open_sink` or `open_stream`
+#[cfg(feature = "pure-rust")]
+pub(crate) enum Sink {
+    /// Used by the next `open`
+    Unopened(CreateSink),
+    /// Held by the backend of the open file
+    Open,
+    /// Returned by the backend when the file was closed
+    Finished(Box<dyn Any + Send>),
+}
+
+impl WriterBackend for Edf {
+    fn set_number_of_annotation_signals(&mut self, annot_signals: usize) -> Result<()> {
+        Edf::set_number_of_annotation_signals(self, annot_signals)
+    }
+
+    fn write_physical_records(&mut self, samples: &[f64], samples_per_record: usize) -> Result<()> {
+        self.blockwrite_physical_samples(samples, samples_per_record)
+    }
+
+    fn write_digital_records(&mut self, samples: &[i32], samples_per_record: usize) -> Result<()> {
+        self.blockwrite_digital_samples(samples, samples_per_record)
+    }
+
+    fn write_annotation(&mut self, onset: i64, duration: i64, description: &str) -> Result<()> {
+        Edf::write_annotation(self, onset, duration, description)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.close()
+    }
+}
+
+/// Reads the samples and annotations of a file opened by an [`EDFReader`](crate::EDFReader).
+///
+/// Every signal has its own sample position, shared by everything that reads the signal.
+pub(crate) trait ReaderBackend: Send {
+    fn context(&self, operation: &'static str, edfsignal: Option<i32>) -> ErrorContext;
+
+    /// Reads physical samples from the sample position, returns the number of samples read.
+    fn read_physical_samples(&self, edfsignal: i32, buf: &mut [f64]) -> Result<usize>;
+
+    /// Reads digital samples from the sample position, returns the number of samples read.
+    fn read_digital_samples(&self, edfsignal: i32, buf: &mut [i32]) -> Result<usize>;
+
+    /// Moves the sample position, clamped to the samples of the signal, and returns it.
+    fn seek(&self, edfsignal: i32, pos: SeekFrom) -> Result<i64>;
+
+    fn tell(&self, edfsignal: i32) -> Result<i64>;
+
+    fn rewind(&self, edfsignal: i32) -> Result<()>;
+
+    /// Returns annotation `n` of the annotations read when the file was opened.
+    fn annotation(&self, n: i64) -> Result<EDFAnnotation>;
+}
+
+impl ReaderBackend for Edf {
+    fn context(&self, operation: &'static str, edfsignal: Option<i32>) -> ErrorContext {
+        Edf::context(self, operation, edfsignal)
+    }
+
+    fn read_physical_samples(&self, edfsignal: i32, buf: &mut [f64]) -> Result<usize> {
+        Edf::read_physical_samples(self, edfsignal, buf)
+    }
+
+    fn read_digital_samples(&self, edfsignal: i32, buf: &mut [i32]) -> Result<usize> {
+        Edf::read_digital_samples(self, edfsignal, buf)
+    }
+
+    fn seek(&self, edfsignal: i32, pos: SeekFrom) -> Result<i64> {
+        Edf::seek(self, edfsignal, pos)
+    }
+
+    fn tell(&self, edfsignal: i32) -> Result<i64> {
+        Edf::tell(self, edfsignal)
+    }
+
+    fn rewind(&self, edfsignal: i32) -> Result<()> {
+        Edf::rewind(self, edfsignal)
+    }
+
+    fn annotation(&self, n: i64) -> Result<EDFAnnotation> {
+        let annot = self.get_annotation(n as i32)?;
+        Ok(EDFAnnotation {
+            onset: from_edflib_time(annot.onset),
+            duration: (annot.duration_l >= 0).then(|| from_edflib_time(annot.duration_l)),
+            description: chars_to_string(&annot.annotation),
+        })
+    }
+}
+
+/// Creates the file with edflib and passes the header settings to it.
+fn create_edflib(settings: &WriterSettings) -> Result<Edf> {
+    let header = settings.header;
+    let patient = &header.patient_info;
+    let mut edf = Edf::open_file_writeonly_with_format(
+        settings.file_path.to_path_buf(),
+        settings.format,
+        header.channels.len() as i32,
+    )?;
+    edf.set_overlong_text(settings.overlong_text);
+
+    edf.set_equipment(patient.equipment.clone())?;
+    edf.set_patientname(patient.patient_name.clone())?;
+    edf.set_patientcode(patient.patient_code.clone())?;
+    edf.set_patient_additional(patient.patient_additional.clone())?;
+    if patient.sex >= 0 {
+        edf.set_sex(patient.sex)?;
+    }
+    if let Some(birthdate) = patient.birthdate {
+        edf.set_birthdate(birthdate)?;
+    }
+    edf.set_admincode(patient.admin_code.clone())?;
+    edf.set_technician(patient.technician.clone())?;
+    edf.set_recording_additional(patient.recording_additional.clone())?;
+    if let Some(start_datetime) = header.start_datetime {
+        // 整秒部分和亚秒部分分开写入
+        edf.set_startdatetime(start_datetime)?;
+        edf.set_subsecond_starttime(start_datetime.subsecond())?;
+    }
+    if let Some(position) = header.annotation_position {
+        edf.set_annot_chan_idx_pos(position)?;
+    }
+
+    for (i, ch) in header.channels.iter().enumerate() {
+        edf.set_label(i as i32, ch.label.clone())?;
+        edf.set_transducer(i as i32, ch.transducer.clone())?;
+        edf.set_digital_maximum(i as i32, ch.digital_max)?;
+        edf.set_digital_minimum(i as i32, ch.digital_min)?;
+        edf.set_physical_maximum(i as i32, ch.physical_max)?;
+        edf.set_physical_minimum(i as i32, ch.physical_min)?;
+        edf.set_physical_dimension(i as i32, ch.physical_dimension.clone())?;
+        edf.set_prefilter(i as i32, ch.prefilter.clone())?;
+        edf.set_samplefrequency(i as i32, settings.samples_in_datarecord[i] as i32)?;
+    }
+    if settings.datarecord_duration < MICRO_DATARECORD_LIMIT {
+        edf.set_micro_datarecord_duration(settings.datarecord_duration)?;
+    } else {
+        edf.set_recordingduration(settings.datarecord_duration)?;
+    }
+
+    Ok(edf)
+}
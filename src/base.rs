@@ -1,37 +1,145 @@
 use std::{
-    ops::Deref,
+    alloc::{alloc_zeroed, handle_alloc_error, Layout},
+    cell::Cell,
+    ffi::{c_void, CString},
+    io::SeekFrom,
+    marker::PhantomData,
     os::raw::c_int,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
     time::Duration,
 };
 
+use crate::datetime::*;
+use crate::error::*;
 use crate::utils::*;
-use anyhow::{anyhow, Result};
-use derive_new::new;
 use edflib_sys::*;
 
-pub enum Filetype {
-    EDF,
-    BDF,
+/// Maximum length in bytes of the description of an annotation written by edflib.
+///
+/// edflib truncates longer descriptions (`EDFLIB_WRITE_MAX_ANNOTATION_LEN` in edflib.c), although it
+/// reads descriptions of up to `EDFLIB_MAX_ANNOTATION_LEN` bytes.
+pub const MAX_WRITE_ANNOTATION_LEN: usize = 40;
+
+/// Maximum number of annotation channels of a file written by edflib.
+pub const MAX_ANNOTATION_CHANNELS: usize = 64;
+
+/// Maximum number of files edflib keeps open at the same time, across all threads.
+pub const MAX_OPEN_FILES: usize = 64;
+
+/// Guards edflib's global table of open files.
+///
+/// Only the calls that scan or change the table take the lock: opening and closing a file, and
+/// the open-file queries. Every other call only reads the slot of its own handle, which no other
+/// thread changes while the `Edf` that owns it is alive, so it takes no lock and calls on
+/// different files run in parallel with each other and with opening and closing.
+///
+/// edflib claims and frees a slot inside `edfopen_file_readonly` and `edfclose_file`, around
+/// parsing the annotations of a file and writing them, so the lock is held for the whole call:
+/// opening and closing files is serialised, reading and writing samples is not.
+static FILE_TABLE: Mutex<()> = Mutex::new(());
+
+fn file_table() -> MutexGuard<'static, ()> {
+    // 锁内不会 panic，忽略中毒状态
+    FILE_TABLE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Allocates a zeroed header on the heap, `edf_hdr_struct` is close to 1 MB.
+fn zeroed_header() -> Box<edf_hdr_struct> {
+    let layout = Layout::new::<edf_hdr_struct>();
+    // 全零对这个 C 结构体是合法值
+    unsafe {
+        let ptr = alloc_zeroed(layout) as *mut edf_hdr_struct;
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        Box::from_raw(ptr)
+    }
+}
+
+/// Maximum length in bytes of the free text header fields, e.g. the patient name or the transducer.
+pub(crate) const MAX_FIELD_LEN: usize = 80;
+/// Maximum length in bytes of a signal label.
+pub(crate) const MAX_LABEL_LEN: usize = 16;
+/// Maximum length in bytes of the physical dimension of a signal.
+pub(crate) const MAX_PHYSICAL_DIMENSION_LEN: usize = 8;
+
+/// What happens to text that is longer than the header field or annotation it is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlongText {
+    /// Fail with [`EdfError::InvalidArgument`]
+    #[default]
+    Error,
+    /// Cut the text at the last character that fits
+    Truncate,
+}
+
+/// The format of an EDF/BDF file.
+///
+/// EDF stores 16-bit and BDF 24-bit samples. The "plus" variants add structured patient and
+/// recording fields, a subsecond start time and annotations. edflib writes EDF+ and BDF+ only,
+/// so `OpenEDFWriter` writes plain EDF and BDF with the pure-Rust backend, whatever backend is
+/// selected. This needs the `pure-rust` feature, which is enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    Edf,
+    EdfPlus,
+    Bdf,
+    BdfPlus,
 }
 
-impl Filetype {
-    fn from(ext: &str) -> Self {
-        match ext {
-            "edf" => Filetype::EDF,
-            "bdf" => Filetype::BDF,
-            _ => Filetype::EDF,
+impl FileFormat {
+    /// Returns the format edflib writes for a file with the given extension: BDF+ for "bdf",
+    /// EDF+ otherwise.
+    pub fn from_extension(ext: &str) -> Self {
+        if ext.eq_ignore_ascii_case("bdf") {
+            FileFormat::BdfPlus
+        } else {
+            FileFormat::EdfPlus
+        }
+    }
+
+    /// Returns the usual file extension, "edf" or "bdf".
+    pub fn extension(&self) -> &'static str {
+        if self.is_bdf() {
+            "bdf"
+        } else {
+            "edf"
         }
     }
-    fn as_str(&self) -> &str {
-        match self {
-            Filetype::EDF => "edf",
-            Filetype::BDF => "bdf",
+
+    /// Returns `true` for BDF and BDF+, which store 24-bit samples.
+    pub fn is_bdf(&self) -> bool {
+        matches!(self, FileFormat::Bdf | FileFormat::BdfPlus)
+    }
+
+    /// Returns `true` for EDF+ and BDF+.
+    pub fn is_plus(&self) -> bool {
+        matches!(self, FileFormat::EdfPlus | FileFormat::BdfPlus)
+    }
+
+    pub(crate) fn from_raw(filetype: i32) -> Option<Self> {
+        match filetype as u32 {
+            EDFLIB_FILETYPE_EDF => Some(FileFormat::Edf),
+            EDFLIB_FILETYPE_EDFPLUS => Some(FileFormat::EdfPlus),
+            EDFLIB_FILETYPE_BDF => Some(FileFormat::Bdf),
+            EDFLIB_FILETYPE_BDFPLUS => Some(FileFormat::BdfPlus),
+            _ => None,
         }
     }
+
+    fn to_raw(self) -> c_int {
+        (match self {
+            FileFormat::Edf => EDFLIB_FILETYPE_EDF,
+            FileFormat::EdfPlus => EDFLIB_FILETYPE_EDFPLUS,
+            FileFormat::Bdf => EDFLIB_FILETYPE_BDF,
+            FileFormat::BdfPlus => EDFLIB_FILETYPE_BDFPLUS,
+        }) as c_int
+    }
 }
 
+/// Position of the annotation channels among the signals of an EDF+/BDF+ file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AnnotationPosition {
     Start,
     Middle,
@@ -39,7 +147,7 @@ pub enum AnnotationPosition {
 }
 
 impl AnnotationPosition {
-    fn to_raw(&self) -> i32 {
+    fn to_raw(self) -> i32 {
         (match self {
             AnnotationPosition::Start => EDF_ANNOT_IDX_POS_START,
             AnnotationPosition::Middle => EDF_ANNOT_IDX_POS_MIDDLE,
@@ -48,298 +156,875 @@ impl AnnotationPosition {
     }
 }
 
-#[derive(new)]
-struct Inner {
-    #[new(value = "0")]
-    hdl: i32,
-    #[new(value = "Filetype::EDF")]
-    filetype: Filetype,
+/// Selects which annotations edflib reads when opening an EDF+/BDF+ file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadAnnotations {
+    /// Do not read annotations, which makes opening very large files faster
+    None,
+    /// Read annotations until the one with the description "Recording ends"
+    UntilRecordingEnds,
+    /// Read all annotations
+    All,
+}
+
+impl ReadAnnotations {
+    fn to_raw(self) -> i32 {
+        (match self {
+            ReadAnnotations::None => EDFLIB_DO_NOT_READ_ANNOTATIONS,
+            ReadAnnotations::UntilRecordingEnds => EDFLIB_READ_ANNOTATIONS,
+            ReadAnnotations::All => EDFLIB_READ_ALL_ANNOTATIONS,
+        }) as i32
+    }
 }
 
-#[derive(new)]
+/// An open edflib file handle.
+///
+/// An `Edf` only exists for a successfully opened file, see [`Edf::open_file_writeonly`] and
+/// [`Edf::open_file_readonly`]. The file is closed when the `Edf` is dropped; use
+/// [`Edf::finish`] to find out whether closing succeeded.
+///
+/// An `Edf` can be moved to another thread but not shared between threads, because edflib does
+/// not lock the state of a single file. Different files can be used from different threads.
 pub struct Edf {
     path: PathBuf,
-    #[new(value = "Arc::new(Mutex::new(Inner::new()))")]
-    inner: Arc<Mutex<Inner>>,
-    pub number_of_signals: i32,
+    /// -1 once the file has been closed
+    hdl: c_int,
+    format: FileFormat,
+    overlong_text: OverlongText,
+    number_of_signals: i32,
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl Edf {
-    fn get_hdl(&self) -> i32 {
-        let inner = self.inner.lock().unwrap();
-        inner.hdl
+    pub(crate) fn context(&self, operation: &'static str, edfsignal: Option<i32>) -> ErrorContext {
+        ErrorContext::new(
+            operation,
+            edfsignal.map(|edfsignal| edfsignal as usize),
+            self.path.clone(),
+        )
+    }
+    fn error(&self, code: i32, operation: &'static str, edfsignal: Option<i32>) -> EdfError {
+        EdfError::from_code(code, self.context(operation, edfsignal))
     }
     pub fn get_edflib_version() -> String {
         let version = unsafe { edflib_version() };
         version.to_string()
     }
-    pub fn open_file_writeonly(&self) -> Result<()> {
-        let path = PathBuf::from(self.path.to_str().unwrap());
-        let ext = path.extension().unwrap().to_str().unwrap();
-        let filetype = Filetype::from(ext);
-
-        let path = str_to_char(path.to_str().unwrap());
-        let mut inner = self.inner.lock().unwrap();
-
-        let filetype = match filetype {
-            Filetype::EDF => EDFLIB_FILETYPE_EDFPLUS as c_int,
-            Filetype::BDF => EDFLIB_FILETYPE_BDFPLUS as c_int,
+    /// Returns the number of files edflib has open, in all threads.
+    pub fn number_of_open_files() -> usize {
+        let _table = file_table();
+        unsafe { edflib_get_number_of_open_files() }.max(0) as usize
+    }
+    /// Returns whether edflib has `path` open, in any thread.
+    pub fn is_file_used(path: &Path) -> bool {
+        let Some(c_path) = path_to_cstring(path) else {
+            return false;
         };
-        let hdl = unsafe { edfopen_file_writeonly(path, filetype, self.number_of_signals) };
-        inner.hdl = hdl;
+        let _table = file_table();
+        unsafe { edflib_is_file_used(c_path.as_ptr()) == 1 }
+    }
+    /// Calls edflib with the handle, in parallel with calls on other files.
+    ///
+    /// The call only reads the slot of this handle in the table of open files, see [`FILE_TABLE`].
+    fn call<T>(&self, f: impl FnOnce(c_int) -> T) -> T {
+        f(self.hdl)
+    }
+    /// Fails with [`EdfError::MaxFilesReached`] if no more files can be opened.
+    ///
+    /// Must be called with the file table locked, which the caller keeps while opening.
+    fn check_open_files(path: &Path, operation: &'static str) -> Result<()> {
+        if unsafe { edflib_get_number_of_open_files() } as usize >= MAX_OPEN_FILES {
+            return Err(EdfError::MaxFilesReached {
+                context: ErrorContext::new(operation, None, path.to_path_buf()),
+            });
+        }
+        Ok(())
+    }
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Returns the format the file was opened with.
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+    /// Returns the number of signals of the file, annotation signals excluded.
+    pub fn number_of_signals(&self) -> i32 {
+        self.number_of_signals
+    }
+    /// Selects what happens to text longer than the header field or annotation it is written to.
+    ///
+    /// Over-long text is rejected by default. edflib stores header fields as ASCII, converting
+    /// Latin-1 characters (e.g. "é" becomes "e"); other characters are always rejected.
+    pub fn set_overlong_text(&mut self, overlong_text: OverlongText) {
+        self.overlong_text = overlong_text;
+    }
+    /// Converts `text` for edflib, see [`Edf::set_overlong_text`].
+    fn c_text(
+        &self,
+        text: &str,
+        encoding: TextEncoding,
+        max_len: usize,
+        operation: &'static str,
+        edfsignal: Option<i32>,
+    ) -> Result<CString> {
+        let truncate = self.overlong_text == OverlongText::Truncate;
+        to_cstring(text, encoding, max_len, truncate).map_err(|message| {
+            EdfError::invalid_argument(self.context(operation, edfsignal), message)
+        })
+    }
+    fn c_path(path: &Path, operation: &'static str) -> Result<CString> {
+        path_to_cstring(path).ok_or_else(|| {
+            EdfError::invalid_argument(
+                ErrorContext::new(operation, None, path.to_path_buf()),
+                "path contains a NUL character",
+            )
+        })
+    }
+    /// Creates the file for writing as EDF+ or BDF+, selected by the extension of the path.
+    pub fn open_file_writeonly(path: PathBuf, number_of_signals: i32) -> Result<Self> {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        let format = FileFormat::from_extension(ext.unwrap_or_default());
+        Self::open_file_writeonly_with_format(path, format, number_of_signals)
+    }
+    /// Creates the file for writing in the given format, regardless of the extension of the path.
+    ///
+    /// edflib only writes EDF+ and BDF+, plain EDF and BDF fail with [`EdfError::FiletypeError`].
+    pub fn open_file_writeonly_with_format(
+        path: PathBuf,
+        format: FileFormat,
+        number_of_signals: i32,
+    ) -> Result<Self> {
+        let c_path = Self::c_path(&path, "open_file_writeonly_with_format")?;
+        let table = file_table();
+        Self::check_open_files(&path, "open_file_writeonly_with_format")?;
+        let hdl =
+            unsafe { edfopen_file_writeonly(c_path.as_ptr(), format.to_raw(), number_of_signals) };
+        drop(table);
 
         if hdl < 0 {
-            let msg = format!(
-                "Can not open file \"{}\"for writing",
-                self.path.to_str().unwrap()
-            );
-            Err(anyhow!(msg))
-        } else {
-            Ok(())
+            let context = ErrorContext::new("open_file_writeonly_with_format", None, path);
+            return Err(if hdl == EDFLIB_MALLOC_ERROR {
+                EdfError::MallocError { context }
+            } else {
+                EdfError::from_code(hdl, context)
+            });
+        }
+        Ok(Self {
+            path,
+            hdl,
+            format,
+            overlong_text: OverlongText::default(),
+            number_of_signals,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Opens an existing EDF/EDF+/BDF/BDF+ file for reading.
+    ///
+    /// On success [`Edf::number_of_signals`] returns the number of signals in the file
+    /// (annotation signals excluded) and the header filled in by edflib is returned alongside
+    /// the handle.
+    /// The header is boxed because `edf_hdr_struct` is close to 1 MB.
+    pub fn open_file_readonly(
+        path: PathBuf,
+        read_annotations: ReadAnnotations,
+    ) -> Result<(Self, Box<edf_hdr_struct>)> {
+        let c_path = Self::c_path(&path, "open_file_readonly")?;
+        let mut hdr = zeroed_header();
+        let table = file_table();
+        Self::check_open_files(&path, "open_file_readonly")?;
+        let result =
+            unsafe { edfopen_file_readonly(c_path.as_ptr(), &mut *hdr, read_annotations.to_raw()) };
+        drop(table);
+
+        if result < 0 {
+            // edflib 把错误码放在 filetype 字段中
+            let context = ErrorContext::new("open_file_readonly", None, path);
+            return Err(if hdr.filetype == EDFLIB_MALLOC_ERROR {
+                EdfError::MallocError { context }
+            } else {
+                EdfError::from_code(hdr.filetype, context)
+            });
         }
+
+        let edf = Self {
+            path,
+            hdl: hdr.handle,
+            format: FileFormat::from_raw(hdr.filetype).unwrap_or(FileFormat::Edf),
+            overlong_text: OverlongText::default(),
+            number_of_signals: hdr.edfsignals,
+            _not_sync: PhantomData,
+        };
+        Ok((edf, hdr))
     }
 
-    pub fn finish(&self) -> Result<()> {
-        let result = unsafe { edfclose_file(self.get_hdl()) };
+    /// Closes the file, writing the header and the annotations if it was opened for writing.
+    ///
+    /// Dropping an `Edf` closes the file as well but ignores errors.
+    pub fn finish(mut self) -> Result<()> {
+        self.close()
+    }
+
+    pub(crate) fn close(&mut self) -> Result<()> {
+        if self.hdl < 0 {
+            return Ok(());
+        }
+        // edflib 即使关闭失败也会释放句柄
+        let result = {
+            let _table = file_table();
+            unsafe { edfclose_file(self.hdl) }
+        };
+        self.hdl = -1;
 
         if result < 0 {
-            Err(anyhow!("Error finishing and closing the file"))
+            Err(self.error(result, "finish", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_patientname(&self, patientname: String) -> Result<()> {
-        let patientname = str_to_char(patientname.as_str());
-        let result = unsafe { edf_set_patientname(self.get_hdl(), patientname) };
+        let patientname = self.c_text(
+            &patientname,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_patientname",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_patientname(hdl, patientname.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_patientname"))
+            Err(self.error(result, "set_patientname", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_patientcode(&self, patientcode: String) -> Result<()> {
-        let patientcode = str_to_char(patientcode.as_str());
-        let result = unsafe { edf_set_patientcode(self.get_hdl(), patientcode) };
+        let patientcode = self.c_text(
+            &patientcode,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_patientcode",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_patientcode(hdl, patientcode.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_patientcode"))
+            Err(self.error(result, "set_patientcode", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_admincode(&self, admincode: String) -> Result<()> {
-        let admincode = str_to_char(admincode.as_str());
-        let result = unsafe { edf_set_admincode(self.get_hdl(), admincode) };
+        let admincode = self.c_text(
+            &admincode,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_admincode",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_admincode(hdl, admincode.as_ptr()) });
         if result < 0 {
-            Err(anyhow!("Error setting set_admincode"))
+            Err(self.error(result, "set_admincode", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_technician(&self, technician: String) -> Result<()> {
-        let technician = str_to_char(technician.as_str());
-        let result = unsafe { edf_set_technician(self.get_hdl(), technician) };
+        let technician = self.c_text(
+            &technician,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_technician",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_technician(hdl, technician.as_ptr()) });
+
+        if result < 0 {
+            Err(self.error(result, "set_technician", None))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn set_patient_additional(&self, patient_additional: String) -> Result<()> {
+        let patient_additional = self.c_text(
+            &patient_additional,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_patient_additional",
+            None,
+        )?;
+        let result = self
+            .call(|hdl| unsafe { edf_set_patient_additional(hdl, patient_additional.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_technician"))
+            Err(self.error(result, "set_patient_additional", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_sex(&self, sex: i32) -> Result<()> {
-        let result = unsafe { edf_set_sex(self.get_hdl(), sex) };
+        let result = self.call(|hdl| unsafe { edf_set_sex(hdl, sex) });
+        if result < 0 {
+            Err(self.error(result, "set_sex", None))
+        } else {
+            Ok(())
+        }
+    }
+    /// Sets the birthdate of the patient, the year must be in the range 1800 - 3000.
+    pub fn set_birthdate(&self, birthdate: EDFDate) -> Result<()> {
+        let result = self.call(|hdl| unsafe {
+            edf_set_birthdate(
+                hdl,
+                birthdate.year,
+                birthdate.month as c_int,
+                birthdate.day as c_int,
+            )
+        });
+
         if result < 0 {
-            Err(anyhow!("Error setting set_sex"))
+            Err(self.error(result, "set_birthdate", None))
         } else {
             Ok(())
         }
     }
-    pub fn set_birthdate(&self, birthdate: i32) -> Result<()> {
-        //TODO: check if this is correct
-        panic!("Not implemented")
+
+    /// Sets the start date and time of the recording, the year must be in the range 1985 - 2084.
+    ///
+    /// If it is not called, edflib uses the system time at the moment the header is written.
+    pub fn set_startdatetime(&self, startdatetime: EDFDateTime) -> Result<()> {
+        let result = self.call(|hdl| unsafe {
+            edf_set_startdatetime(
+                hdl,
+                startdatetime.date.year,
+                startdatetime.date.month as c_int,
+                startdatetime.date.day as c_int,
+                startdatetime.hour as c_int,
+                startdatetime.minute as c_int,
+                startdatetime.second as c_int,
+            )
+        });
+
+        if result < 0 {
+            Err(self.error(result, "set_startdatetime", None))
+        } else {
+            Ok(())
+        }
     }
 
-    pub fn set_startdatetime(&self, startdatetime: i32) -> Result<()> {
-        //TODO: check if this is correct
-        panic!("Not implemented")
+    /// Sets the fraction of the second of the recording's start time, in units of 100 ns.
+    ///
+    /// Only EDF+ and BDF+ store it; `subsecond` must be less than 10 000 000 (one second).
+    pub fn set_subsecond_starttime(&self, subsecond: i32) -> Result<()> {
+        let result = self.call(|hdl| unsafe { edf_set_subsecond_starttime(hdl, subsecond) });
+
+        if result < 0 {
+            Err(self.error(result, "set_subsecond_starttime", None))
+        } else {
+            Ok(())
+        }
     }
 
     pub fn set_transducer(&self, edfsignal: i32, transducer: String) -> Result<()> {
-        let transducer = str_to_char(transducer.as_str());
-        let result = unsafe { edf_set_transducer(self.get_hdl(), edfsignal, transducer) };
+        let transducer = self.c_text(
+            &transducer,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_transducer",
+            Some(edfsignal),
+        )?;
+        let result =
+            self.call(|hdl| unsafe { edf_set_transducer(hdl, edfsignal, transducer.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_transducer"))
+            Err(self.error(result, "set_transducer", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_samplefrequency(&self, edfsignal: i32, samplefrequency: i32) -> Result<()> {
-        let result = unsafe { edf_set_samplefrequency(self.get_hdl(), edfsignal, samplefrequency) };
+        let result =
+            self.call(|hdl| unsafe { edf_set_samplefrequency(hdl, edfsignal, samplefrequency) });
         if result < 0 {
-            Err(anyhow!("Error setting set_samplefrequency"))
+            Err(self.error(result, "set_samplefrequency", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_digital_maximum(&self, edfsignal: i32, dig_max: i32) -> Result<()> {
-        let result = unsafe { edf_set_digital_maximum(self.get_hdl(), edfsignal, dig_max) };
+        let result = self.call(|hdl| unsafe { edf_set_digital_maximum(hdl, edfsignal, dig_max) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_digital_maximum"))
+            Err(self.error(result, "set_digital_maximum", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
     pub fn set_physical_maximum(&self, edfsignal: i32, dig_max: f64) -> Result<()> {
-        let result = unsafe { edf_set_physical_maximum(self.get_hdl(), edfsignal, dig_max) };
+        let result = self.call(|hdl| unsafe { edf_set_physical_maximum(hdl, edfsignal, dig_max) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_physical_maximum"))
+            Err(self.error(result, "set_physical_maximum", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_physical_minimum(&self, edfsignal: i32, dig_max: f64) -> Result<()> {
-        let result = unsafe { edf_set_physical_minimum(self.get_hdl(), edfsignal, dig_max) };
+        let result = self.call(|hdl| unsafe { edf_set_physical_minimum(hdl, edfsignal, dig_max) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_physical_minimum"))
+            Err(self.error(result, "set_physical_minimum", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_digital_minimum(&self, edfsignal: i32, dig_min: i32) -> Result<()> {
-        let result = unsafe { edf_set_digital_minimum(self.get_hdl(), edfsignal, dig_min) };
+        let result = self.call(|hdl| unsafe { edf_set_digital_minimum(hdl, edfsignal, dig_min) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_digital_minimum"))
+            Err(self.error(result, "set_digital_minimum", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_physical_dimension(&self, edfsignal: i32, phys_dim: String) -> Result<()> {
-        let phys_dim = str_to_char(phys_dim.as_str());
-        let result = unsafe { edf_set_physical_dimension(self.get_hdl(), edfsignal, phys_dim) };
+        let phys_dim = self.c_text(
+            &phys_dim,
+            TextEncoding::Latin1,
+            MAX_PHYSICAL_DIMENSION_LEN,
+            "set_physical_dimension",
+            Some(edfsignal),
+        )?;
+        let result = self
+            .call(|hdl| unsafe { edf_set_physical_dimension(hdl, edfsignal, phys_dim.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_physical_dimension"))
+            Err(self.error(result, "set_physical_dimension", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_label(&self, edfsignal: i32, label: String) -> Result<()> {
-        let label = str_to_char(label.as_str());
-        let result = unsafe { edf_set_label(self.get_hdl(), edfsignal, label) };
+        let label = self.c_text(
+            &label,
+            TextEncoding::Latin1,
+            MAX_LABEL_LEN,
+            "set_label",
+            Some(edfsignal),
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_label(hdl, edfsignal, label.as_ptr()) });
+
+        if result < 0 {
+            Err(self.error(result, "set_label", Some(edfsignal)))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn set_prefilter(&self, edfsignal: i32, prefilter: String) -> Result<()> {
+        let prefilter = self.c_text(
+            &prefilter,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_prefilter",
+            Some(edfsignal),
+        )?;
+        let result =
+            self.call(|hdl| unsafe { edf_set_prefilter(hdl, edfsignal, prefilter.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_label"))
+            Err(self.error(result, "set_prefilter", Some(edfsignal)))
         } else {
             Ok(())
         }
     }
 
     pub fn set_equipment(&self, equipment: String) -> Result<()> {
-        let equipment = str_to_char(equipment.as_str());
-        let result = unsafe { edf_set_equipment(self.get_hdl(), equipment) };
+        let equipment = self.c_text(
+            &equipment,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_equipment",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe { edf_set_equipment(hdl, equipment.as_ptr()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_equipment"))
+            Err(self.error(result, "set_equipment", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_recording_additional(&self, recording_additional: String) -> Result<()> {
-        let recording_additional = str_to_char(recording_additional.as_str());
-        let result = unsafe { edf_set_recording_additional(self.get_hdl(), recording_additional) };
+        let recording_additional = self.c_text(
+            &recording_additional,
+            TextEncoding::Latin1,
+            MAX_FIELD_LEN,
+            "set_recording_additional",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe {
+            edf_set_recording_additional(hdl, recording_additional.as_ptr())
+        });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_recording_additional"))
+            Err(self.error(result, "set_recording_additional", None))
         } else {
             Ok(())
         }
     }
+    /// Sets the duration of a datarecord, in the range 1 ms to 60 s with a resolution of 10 µs.
+    ///
+    /// See [`Edf::set_micro_datarecord_duration`] for shorter datarecords.
     pub fn set_recordingduration(&self, duration: Duration) -> Result<()> {
-        // Convert duration from seconds to 10 microseconds
-        let duration_in_10_microseconds =
-            (duration.as_secs() as i64 * 100000 + duration.subsec_micros() as i64 / 100) as i32;
+        // edflib 的单位是 10 微秒
+        if !duration.as_nanos().is_multiple_of(10_000) {
+            return Err(EdfError::invalid_argument(
+                self.context("set_recordingduration", None),
+                "datarecord duration must be a multiple of 10 microseconds",
+            ));
+        }
+        let duration_in_10_microseconds = duration.as_nanos() / 10_000;
 
         // Ensure the duration is within the valid range
-        if duration_in_10_microseconds < 100 || duration_in_10_microseconds > 6000000 {
-            return Err(anyhow!(
-                "Datarecord duration must be in the range 0.001 to 60 seconds"
+        if !(100..=6000000).contains(&duration_in_10_microseconds) {
+            return Err(EdfError::invalid_argument(
+                self.context("set_recordingduration", None),
+                "datarecord duration must be in the range 0.001 to 60 seconds",
             ));
         }
+        let duration_in_10_microseconds = duration_in_10_microseconds as i32;
 
-        let result =
-            unsafe { edf_set_datarecord_duration(self.get_hdl(), duration_in_10_microseconds) };
+        let result = self
+            .call(|hdl| unsafe { edf_set_datarecord_duration(hdl, duration_in_10_microseconds) });
+        if result < 0 {
+            Err(self.error(result, "set_recordingduration", None))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the duration of a datarecord, in the range 1 µs to 9999 µs with a resolution of 1 µs.
+    pub fn set_micro_datarecord_duration(&self, duration: Duration) -> Result<()> {
+        if !duration.as_nanos().is_multiple_of(1_000) || !(1..=9999).contains(&duration.as_micros())
+        {
+            return Err(EdfError::invalid_argument(
+                self.context("set_micro_datarecord_duration", None),
+                "datarecord duration must be a whole number of microseconds from 1 to 9999",
+            ));
+        }
+
+        let result = self.call(|hdl| unsafe {
+            edf_set_micro_datarecord_duration(hdl, duration.as_micros() as i32)
+        });
         if result < 0 {
-            Err(anyhow!("Error setting datarecord duration"))
+            Err(self.error(result, "set_micro_datarecord_duration", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_annot_chan_idx_pos(&self, position: AnnotationPosition) -> Result<()> {
-        let result = unsafe { edf_set_annot_chan_idx_pos(self.get_hdl(), position.to_raw()) };
+        let result = self.call(|hdl| unsafe { edf_set_annot_chan_idx_pos(hdl, position.to_raw()) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_annot_chan_idx_pos"))
+            Err(self.error(result, "set_annot_chan_idx_pos", None))
         } else {
             Ok(())
         }
     }
 
     pub fn set_number_of_annotation_signals(&self, annot_signals: usize) -> Result<()> {
-        let result =
-            unsafe { edf_set_number_of_annotation_signals(self.get_hdl(), annot_signals as i32) };
+        let result = self
+            .call(|hdl| unsafe { edf_set_number_of_annotation_signals(hdl, annot_signals as i32) });
 
         if result < 0 {
-            Err(anyhow!("Error setting set_number_of_annotation_signals"))
+            Err(self.error(result, "set_number_of_annotation_signals", None))
         } else {
             Ok(())
         }
     }
 
-    pub fn write_samples(&self, samples: &mut Vec<f64>, samplefrequency: usize) -> Result<()> {
-        if (samples.len() / samplefrequency != 1) {
-            return Err(anyhow!(
-                "samples length must be a full sample of samplefrequency"
+    /// Checks that `len` samples make up one or more whole datarecords of `samplefrequency` samples.
+    fn check_samples_len(
+        &self,
+        len: usize,
+        samplefrequency: usize,
+        operation: &'static str,
+    ) -> Result<()> {
+        if len == 0 || samplefrequency == 0 || !len.is_multiple_of(samplefrequency) {
+            return Err(EdfError::invalid_argument(
+                self.context(operation, None),
+                "samples length must be a full sample of samplefrequency",
             ));
         }
+        Ok(())
+    }
+
+    pub fn write_samples(&self, samples: &mut [f64], samplefrequency: usize) -> Result<()> {
+        self.check_samples_len(samples.len(), samplefrequency, "write_samples")?;
         // 分批写入样本
         for chunk in samples.chunks_mut(samplefrequency) {
             let buf: *mut f64 = chunk.as_mut_ptr().cast::<f64>();
-            let result = unsafe { edfwrite_physical_samples(self.get_hdl(), buf) };
+            let result = self.call(|hdl| unsafe { edfwrite_physical_samples(hdl, buf) });
             if result < 0 {
-                return Err(anyhow!("Error writing samples"));
+                return Err(self.error(result, "write_samples", None));
             }
         }
         Ok(())
     }
 
-    pub fn write_annotation(&self, onset: i64, duration: i64, description: String) -> Result<()> {
-        let description = str_to_char(description.as_str());
-        let result =
-            unsafe { edfwrite_annotation_latin1_hr(self.get_hdl(), onset, duration, description) };
+    /// Writes 16-bit digital samples of the next signal, `samplefrequency` samples per datarecord.
+    ///
+    /// Only EDF files accept 16-bit samples. edflib clamps out-of-range values to the signal's
+    /// digital minimum and maximum in place.
+    pub fn write_digital_short_samples(
+        &self,
+        samples: &mut [i16],
+        samplefrequency: usize,
+    ) -> Result<()> {
+        self.check_samples_len(
+            samples.len(),
+            samplefrequency,
+            "write_digital_short_samples",
+        )?;
+        for chunk in samples.chunks_mut(samplefrequency) {
+            let result =
+                self.call(|hdl| unsafe { edfwrite_digital_short_samples(hdl, chunk.as_mut_ptr()) });
+            if result < 0 {
+                return Err(self.error(result, "write_digital_short_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes digital samples of the next signal, `samplefrequency` samples per datarecord.
+    ///
+    /// edflib clamps out-of-range values to the signal's digital minimum and maximum in place.
+    pub fn write_digital_samples(&self, samples: &mut [i32], samplefrequency: usize) -> Result<()> {
+        self.check_samples_len(samples.len(), samplefrequency, "write_digital_samples")?;
+        for chunk in samples.chunks_mut(samplefrequency) {
+            let result =
+                self.call(|hdl| unsafe { edfwrite_digital_samples(hdl, chunk.as_mut_ptr()) });
+            if result < 0 {
+                return Err(self.error(result, "write_digital_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes whole datarecords from physical samples.
+    ///
+    /// `buf` holds the samples of all signals one after the other, `samples_per_record` is the
+    /// total number of samples of all signals in one datarecord.
+    pub fn blockwrite_physical_samples(
+        &self,
+        buf: &[f64],
+        samples_per_record: usize,
+    ) -> Result<()> {
+        self.check_samples_len(buf.len(), samples_per_record, "blockwrite_physical_samples")?;
+        for chunk in buf.chunks(samples_per_record) {
+            // edflib 只读取缓冲区，不会修改
+            let result = self.call(|hdl| unsafe {
+                edf_blockwrite_physical_samples(hdl, chunk.as_ptr() as *mut f64)
+            });
+            if result < 0 {
+                return Err(self.error(result, "blockwrite_physical_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes whole datarecords of an EDF file from 16-bit digital samples.
+    ///
+    /// The layout of `buf` is the same as for [`Edf::blockwrite_physical_samples`]. edflib clamps
+    /// out-of-range values to the signal's digital minimum and maximum in place.
+    pub fn blockwrite_digital_short_samples(
+        &self,
+        buf: &mut [i16],
+        samples_per_record: usize,
+    ) -> Result<()> {
+        self.check_samples_len(
+            buf.len(),
+            samples_per_record,
+            "blockwrite_digital_short_samples",
+        )?;
+        for chunk in buf.chunks_mut(samples_per_record) {
+            let result = self.call(|hdl| unsafe {
+                edf_blockwrite_digital_short_samples(hdl, chunk.as_mut_ptr())
+            });
+            if result < 0 {
+                return Err(self.error(result, "blockwrite_digital_short_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes whole datarecords from digital samples.
+    ///
+    /// The layout of `buf` is the same as for [`Edf::blockwrite_physical_samples`].
+    pub fn blockwrite_digital_samples(&self, buf: &[i32], samples_per_record: usize) -> Result<()> {
+        self.check_samples_len(buf.len(), samples_per_record, "blockwrite_digital_samples")?;
+        for chunk in buf.chunks(samples_per_record) {
+            // edflib 只读取缓冲区，不会修改
+            let result = self.call(|hdl| unsafe {
+                edf_blockwrite_digital_samples(hdl, chunk.as_ptr() as *mut c_int)
+            });
+            if result < 0 {
+                return Err(self.error(result, "blockwrite_digital_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes whole datarecords of a BDF file from 24-bit little-endian samples.
+    ///
+    /// `buf` holds the samples of all signals one after the other, `samples_per_record` is the
+    /// total number of samples of all signals in one datarecord. The values are not clamped.
+    pub fn blockwrite_digital_3byte_samples(
+        &self,
+        buf: &[u8],
+        samples_per_record: usize,
+    ) -> Result<()> {
+        self.check_samples_len(
+            buf.len(),
+            samples_per_record * 3,
+            "blockwrite_digital_3byte_samples",
+        )?;
+        for chunk in buf.chunks(samples_per_record * 3) {
+            // edflib 只读取缓冲区，不会修改
+            let result = self.call(|hdl| unsafe {
+                edf_blockwrite_digital_3byte_samples(hdl, chunk.as_ptr() as *mut c_void)
+            });
+            if result < 0 {
+                return Err(self.error(result, "blockwrite_digital_3byte_samples", None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a UTF-8 annotation.
+    ///
+    /// `onset` and `duration` are in microseconds, a `duration` of -1 means unknown or not applicable.
+    /// `description` may be at most [`MAX_WRITE_ANNOTATION_LEN`] bytes long, see [`Edf::set_overlong_text`].
+    pub fn write_annotation(&self, onset: i64, duration: i64, description: &str) -> Result<()> {
+        let description = self.c_text(
+            description,
+            TextEncoding::Utf8,
+            MAX_WRITE_ANNOTATION_LEN,
+            "write_annotation",
+            None,
+        )?;
+        let result = self.call(|hdl| unsafe {
+            edfwrite_annotation_utf8_hr(hdl, onset, duration, description.as_ptr())
+        });
 
         if result < 0 {
-            Err(anyhow!("Error write_annotation"))
+            Err(self.error(result, "write_annotation", None))
         } else {
             Ok(())
         }
     }
+
+    /// Reads `buf.len()` physical samples of `edfsignal` starting at its sample position indicator.
+    ///
+    /// Returns the number of samples read, which is less than `buf.len()` near the end of the file.
+    pub fn read_physical_samples(&self, edfsignal: i32, buf: &mut [f64]) -> Result<usize> {
+        let n = buf.len().min(c_int::MAX as usize) as c_int;
+        let result = self
+            .call(|hdl| unsafe { edfread_physical_samples(hdl, edfsignal, n, buf.as_mut_ptr()) });
+
+        if result < 0 {
+            Err(self.error(result, "read_physical_samples", Some(edfsignal)))
+        } else {
+            Ok(result as usize)
+        }
+    }
+
+    /// Reads `buf.len()` digital (raw ADC) samples of `edfsignal` starting at its sample position indicator.
+    ///
+    /// Returns the number of samples read, which is less than `buf.len()` near the end of the file.
+    pub fn read_digital_samples(&self, edfsignal: i32, buf: &mut [i32]) -> Result<usize> {
+        let n = buf.len().min(c_int::MAX as usize) as c_int;
+        let result = self
+            .call(|hdl| unsafe { edfread_digital_samples(hdl, edfsignal, n, buf.as_mut_ptr()) });
+
+        if result < 0 {
+            Err(self.error(result, "read_digital_samples", Some(edfsignal)))
+        } else {
+            Ok(result as usize)
+        }
+    }
+
+    /// Moves the sample position indicator of `edfsignal`, measured in samples.
+    ///
+    /// edflib clamps the new position to the range `0..=samples in file`.
+    /// Returns the new position.
+    pub fn seek(&self, edfsignal: i32, pos: SeekFrom) -> Result<i64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset.min(i64::MAX as u64) as i64, EDFSEEK_SET),
+            SeekFrom::Current(offset) => (offset, EDFSEEK_CUR),
+            SeekFrom::End(offset) => (offset, EDFSEEK_END),
+        };
+        let result = self.call(|hdl| unsafe { edfseek(hdl, edfsignal, offset, whence as c_int) });
+
+        if result < 0 {
+            Err(self.error(result as i32, "seek", Some(edfsignal)))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Returns the sample position indicator of `edfsignal`.
+    pub fn tell(&self, edfsignal: i32) -> Result<i64> {
+        let result = self.call(|hdl| unsafe { edftell(hdl, edfsignal) });
+
+        if result < 0 {
+            Err(self.error(result as i32, "tell", Some(edfsignal)))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Moves the sample position indicator of `edfsignal` to the start of the file.
+    pub fn rewind(&self, edfsignal: i32) -> Result<()> {
+        let result = self.call(|hdl| unsafe { edfrewind(hdl, edfsignal) });
+
+        if result < 0 {
+            Err(self.error(result, "rewind", Some(edfsignal)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns annotation `n` of a file opened for reading.
+    pub fn get_annotation(&self, n: i32) -> Result<edf_annotation_struct> {
+        let mut annot: edf_annotation_struct = unsafe { std::mem::zeroed() };
+        let result = self.call(|hdl| unsafe { edf_get_annotation(hdl, n, &mut annot) });
+
+        if result < 0 {
+            Err(self.error(result, "get_annotation", None))
+        } else {
+            Ok(annot)
+        }
+    }
+}
+
+impl Drop for Edf {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
 }
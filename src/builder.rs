@@ -0,0 +1,252 @@
+use crate::backend::*;
+use crate::base::*;
+use crate::datetime::*;
+use crate::error::*;
+use crate::writer::*;
+#[cfg(feature = "pure-rust")]
+use std::io::{Seek, Write};
+use std::{path::PathBuf, time::Duration};
+
+/// Collects the header of a new EDF+/BDF+ file and opens an [`OpenEDFWriter`] for it.
+///
+/// The header can only be changed here: edflib ignores header changes once samples have been
+/// written, so the [`OpenEDFWriter`] returned by [`EDFWriterBuilder::open`] only writes samples
+/// and annotations. Every setting is checked before the file is created.
+///
+/// # Example
+///
+/// ```no_run
+/// # use edflib::{EDFChannel, EDFWriterBuilder};
+/// # fn main() -> edflib::Result<()> {
+/// let channel = EDFChannel {
+///     label: "EEG Fp1".to_string(),
+///     transducer: "AgAgCl cup electrodes".to_string(),
+///     digital_max: 32767,
+///     digital_min: -32768,
+///     physical_max: 3000.0,
+///     physical_min: -3000.0,
+///     physical_dimension: "uV".to_string(),
+///     prefilter: "HP:0.1Hz LP:75Hz".to_string(),
+///     sample_frequency: 256.0,
+/// };
+/// let mut writer = EDFWriterBuilder::new("output.edf".into())
+///     .channel(channel)
+///     .open()?;
+/// writer.write_sample_stream(&[vec![0.0; 256]])?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EDFWriterBuilder {
+    file_path: PathBuf,
+    header: EDFHeader,
+    final_record_policy: FinalRecordPolicy,
+    overlong_text: OverlongText,
+    backend: Backend,
+}
+
+impl EDFWriterBuilder {
+    /// Starts with empty patient information, no channels and the defaults of [`EDFHeader`].
+    pub fn new(file_path: PathBuf) -> Self {
+        Self::from_header(
+            file_path,
+            EDFHeader {
+                patient_info: EDFPatientInfo::default(),
+                channels: Vec::new(),
+                start_datetime: None,
+                datarecord_duration: None,
+                annotation_channels: None,
+                annotation_position: None,
+                file_format: None,
+            },
+        )
+    }
+
+    /// Starts with an existing header, e.g. the one of an [`EDFReader`](crate::EDFReader).
+    pub fn from_header(file_path: PathBuf, header: EDFHeader) -> Self {
+        Self {
+            file_path,
+            header,
+            final_record_policy: FinalRecordPolicy::default(),
+            overlong_text: OverlongText::default(),
+            backend: Backend::default(),
+        }
+    }
+
+    pub fn patient_info(mut self, patient_info: EDFPatientInfo) -> Self {
+        self.header.patient_info = patient_info;
+        self
+    }
+
+    /// Appends a channel, channels are written in the order they are added.
+    pub fn channel(mut self, channel: EDFChannel) -> Self {
+        self.header.channels.push(channel);
+        self
+    }
+
+    /// Appends several channels.
+    pub fn channels(mut self, channels: impl IntoIterator<Item = EDFChannel>) -> Self {
+        self.header.channels.extend(channels);
+        self
+    }
+
+    /// Sets the start of the recording, edflib uses the system time when the file is closed otherwise.
+    pub fn start_datetime(mut self, start_datetime: EDFDateTime) -> Self {
+        self.header.start_datetime = Some(start_datetime);
+        self
+    }
+
+    /// See [`EDFHeader::datarecord_duration`].
+    pub fn datarecord_duration(mut self, datarecord_duration: Duration) -> Self {
+        self.header.datarecord_duration = Some(datarecord_duration);
+        self
+    }
+
+    /// See [`EDFHeader::annotation_channels`].
+    pub fn annotation_channels(mut self, annotation_channels: usize) -> Self {
+        self.header.annotation_channels = Some(annotation_channels);
+        self
+    }
+
+    pub fn annotation_position(mut self, annotation_position: AnnotationPosition) -> Self {
+        self.header.annotation_position = Some(annotation_position);
+        self
+    }
+
+    /// See [`EDFHeader::file_format`].
+    pub fn file_format(mut self, file_format: FileFormat) -> Self {
+        self.header.file_format = Some(file_format);
+        self
+    }
+
+    /// Sets what [`OpenEDFWriter::finish`] does with pushed samples that do not fill a whole
+    /// datarecord.
+    pub fn final_record_policy(mut self, policy: FinalRecordPolicy) -> Self {
+        self.final_record_policy = policy;
+        self
+    }
+
+    /// Sets what happens to header fields and annotations that are too long.
+    ///
+    /// By default [`EDFWriterBuilder::open`] and [`OpenEDFWriter::write_annotation`] fail on
+    /// over-long text.
+    pub fn overlong_text(mut self, overlong_text: OverlongText) -> Self {
+        self.overlong_text = overlong_text;
+        self
+    }
+
+    /// Selects the implementation that writes the file.
+    ///
+    /// edflib is used by default. `Backend::PureRust` writes the same bytes without calling
+    /// into edflib, so it is not limited by edflib's table of open files. Writers with a sink
+    /// and plain EDF and BDF files always use the pure-Rust backend.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Returns the header collected so far.
+    pub fn header(&self) -> &EDFHeader {
+        &self.header
+    }
+
+    /// Checks the header without creating the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidArgument`] if there are no channels, a channel has
+    /// `digital_min >= digital_max`, equal physical limits, a label longer than 16 bytes, a
+    /// physical dimension longer than 8 bytes or a sample frequency that is not positive, or if
+    /// any other field cannot be written.
+    pub fn validate(&self) -> Result<()> {
+        self.writer().check_header().map(|_| ())
+    }
+
+    /// Checks the header, creates the file and writes the header settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`EDFWriterBuilder::validate`] and errors creating the file.
+    pub fn open(self) -> Result<OpenEDFWriter> {
+        let mut writer = self.writer();
+        writer.open()?;
+        Ok(writer)
+    }
+
+    /// Writes to `sink` instead of a file, e.g. a `Cursor<Vec<u8>>`.
+    ///
+    /// The file is written by the pure-Rust backend like [`Backend::PureRust`] writes a file, and
+    /// the header and the annotations are completed by seeking back when the writer is finished.
+    /// [`OpenEDFWriter::into_sink`] returns the sink after [`OpenEDFWriter::finish`]. The path
+    /// is only used in error messages and `header.file_format` selects EDF+ or BDF+, EDF+ if it
+    /// is `None`. Only available with the `pure-rust` feature.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::{io::Cursor, path::PathBuf};
+    /// # use edflib::{EDFHeader, EDFWriterBuilder};
+    /// # fn header() -> EDFHeader { unimplemented!() }
+    /// # fn main() -> edflib::Result<()> {
+    /// let mut writer = EDFWriterBuilder::from_header(PathBuf::new(), header())
+    ///     .open_sink(Cursor::new(Vec::new()))?;
+    /// writer.write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])?;
+    /// writer.finish()?;
+    /// let bytes = writer.into_sink::<Cursor<Vec<u8>>>().unwrap().into_inner();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`EDFWriterBuilder::open`].
+    #[cfg(feature = "pure-rust")]
+    pub fn open_sink<W: Write + Seek + Send + 'static>(self, sink: W) -> Result<OpenEDFWriter> {
+        let writer = OpenEDFWriter::with_sink(self.file_path.clone(), sink, self.header.clone());
+        self.configure(writer)
+    }
+
+    /// Writes `datarecords` datarecords to a sink that cannot seek, e.g. a socket.
+    ///
+    /// Unlike [`EDFWriterBuilder::open_sink`] nothing is written twice: the number of
+    /// datarecords is written into the header up front and every datarecord holds the
+    /// annotations written before it, as many as there are annotation channels.
+    /// [`OpenEDFWriter::finish`] fails if a different number of datarecords was written or
+    /// annotations are left over, and writing more datarecords than declared fails. Only
+    /// available with the `pure-rust` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`EDFWriterBuilder::open`].
+    #[cfg(feature = "pure-rust")]
+    pub fn open_stream<W: Write + Send + 'static>(
+        self,
+        sink: W,
+        datarecords: usize,
+    ) -> Result<OpenEDFWriter> {
+        let writer = OpenEDFWriter::with_stream(
+            self.file_path.clone(),
+            sink,
+            self.header.clone(),
+            datarecords,
+        );
+        self.configure(writer)
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn configure(&self, mut writer: OpenEDFWriter) -> Result<OpenEDFWriter> {
+        writer.set_final_record_policy(self.final_record_policy);
+        writer.set_overlong_text(self.overlong_text);
+        writer.open()?;
+        Ok(writer)
+    }
+
+    fn writer(&self) -> OpenEDFWriter {
+        let mut writer = OpenEDFWriter::new(self.file_path.clone(), self.header.clone());
+        writer.set_final_record_policy(self.final_record_policy);
+        writer.set_overlong_text(self.overlong_text);
+        writer.set_backend(self.backend);
+        writer
+    }
+}
@@ -0,0 +1,225 @@
+use crate::backend::*;
+use crate::error::*;
+use std::{
+    io::{self, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// Enumeration of possible methods to seek within a signal by time.
+///
+/// This is the time based counterpart of `std::io::SeekFrom`; since `Duration` cannot be
+/// negative, relative seeks are split into a forward and a backward variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSeekFrom {
+    /// Sets the position to the given time after the start of the recording.
+    Start(Duration),
+    /// Sets the position to the given time before the end of the recording.
+    End(Duration),
+    /// Moves the position forward by the given time.
+    Forward(Duration),
+    /// Moves the position backward by the given time.
+    Backward(Duration),
+}
+
+/// A seekable read position within a single channel of an `EDFReader`.
+///
+/// Positions are expressed in samples (through `std::io::Seek`) or in time (through
+/// [`SignalCursor::seek_time`]). Reads start at the current position and advance it.
+///
+/// Every channel has a single sample position, so cursors created for the same channel share
+/// their position, as do `EDFReader::read_physical_samples` and `EDFReader::read_digital_samples`.
+pub struct SignalCursor<'a> {
+    edf: &'a dyn ReaderBackend,
+    edfsignal: i32,
+    samples_in_file: i64,
+    samples_in_datarecord: i32,
+    datarecord_duration: Duration,
+}
+
+impl<'a> SignalCursor<'a> {
+    pub(crate) fn new(
+        edf: &'a dyn ReaderBackend,
+        edfsignal: i32,
+        samples_in_file: i64,
+        samples_in_datarecord: i32,
+        datarecord_duration: Duration,
+    ) -> Self {
+        Self {
+            edf,
+            edfsignal,
+            samples_in_file,
+            samples_in_datarecord,
+            datarecord_duration,
+        }
+    }
+
+    /// Returns the number of samples of the channel.
+    pub fn len(&self) -> u64 {
+        self.samples_in_file as u64
+    }
+
+    /// Returns `true` if the channel contains no samples.
+    pub fn is_empty(&self) -> bool {
+        self.samples_in_file == 0
+    }
+
+    /// Returns the duration covered by the samples of the channel, zero if a datarecord holds
+    /// no samples of it.
+    pub fn duration(&self) -> Duration {
+        self.samples_to_time(self.len(), "duration")
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Returns the number of samples per second of the channel.
+    pub fn sample_frequency(&self) -> f64 {
+        self.samples_in_datarecord as f64 / self.datarecord_duration.as_secs_f64()
+    }
+
+    /// Returns the current position in samples.
+    pub fn position(&self) -> Result<u64> {
+        Ok(self.edf.tell(self.edfsignal)? as u64)
+    }
+
+    /// Returns the time of the sample at the current position.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidArgument`] if a datarecord holds no samples of the channel.
+    pub fn time(&self) -> Result<Duration> {
+        self.samples_to_time(self.position()?, "time")
+    }
+
+    /// Moves the position back to the first sample.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.edf.rewind(self.edfsignal)
+    }
+
+    /// Moves the position to the first sample at or after the given time.
+    ///
+    /// Positions past the end of the channel are clamped to the end.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new position would lie before the start of the recording, or
+    /// [`EdfError::InvalidArgument`] if the datarecords have a duration of 0.
+    ///
+    /// # Returns
+    ///
+    /// The new position in samples.
+    pub fn seek_time(&mut self, pos: TimeSeekFrom) -> Result<u64> {
+        let operation = "seek_time";
+        let target = match pos {
+            TimeSeekFrom::Start(time) => Some(self.time_to_samples(time, operation)?),
+            TimeSeekFrom::End(time) => self
+                .len()
+                .checked_sub(self.time_to_samples(time, operation)?),
+            TimeSeekFrom::Forward(time) => Some(
+                self.position()?
+                    .saturating_add(self.time_to_samples(time, operation)?),
+            ),
+            TimeSeekFrom::Backward(time) => self
+                .position()?
+                .checked_sub(self.time_to_samples(time, operation)?),
+        };
+        match target {
+            Some(target) => Ok(self.edf.seek(self.edfsignal, SeekFrom::Start(target))? as u64),
+            None => Err(EdfError::invalid_argument(
+                self.edf.context(operation, Some(self.edfsignal)),
+                "can not seek before the start of the recording",
+            )),
+        }
+    }
+
+    /// Reads physical samples into `buf`, starting at the current position.
+    ///
+    /// # Returns
+    ///
+    /// The number of samples read; this is less than `buf.len()` (possibly zero) at the end of the channel.
+    pub fn read_physical(&mut self, buf: &mut [f64]) -> Result<usize> {
+        self.edf.read_physical_samples(self.edfsignal, buf)
+    }
+
+    /// Reads digital (raw ADC) samples into `buf`, starting at the current position.
+    ///
+    /// # Returns
+    ///
+    /// The number of samples read; this is less than `buf.len()` (possibly zero) at the end of the channel.
+    pub fn read_digital(&mut self, buf: &mut [i32]) -> Result<usize> {
+        self.edf.read_digital_samples(self.edfsignal, buf)
+    }
+
+    /// Reads the physical samples in the time window `start..start + length`.
+    ///
+    /// The window is truncated at the end of the channel. Afterwards the position is at the end of the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidArgument`] if the datarecords have a duration of 0 or if
+    /// `start + length` overflows.
+    pub fn read_window(&mut self, start: Duration, length: Duration) -> Result<Vec<f64>> {
+        let Some(end) = start.checked_add(length) else {
+            return Err(EdfError::invalid_argument(
+                self.edf.context("read_window", Some(self.edfsignal)),
+                "the end of the window overflows",
+            ));
+        };
+        let first = self.seek_time(TimeSeekFrom::Start(start))?;
+        let last = self.time_to_samples(end, "read_window")?.min(self.len());
+        let mut buf = vec![0.0; last.saturating_sub(first) as usize];
+        let n = self.read_physical(&mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Converts a time into the index of the first sample at or after it.
+    fn time_to_samples(&self, time: Duration, operation: &'static str) -> Result<u64> {
+        let record_nanos = self.datarecord_duration.as_nanos();
+        if record_nanos == 0 {
+            // 时长为 0 的数据记录无法换算时间
+            return Err(EdfError::invalid_argument(
+                self.edf.context(operation, Some(self.edfsignal)),
+                "the datarecords have a duration of 0",
+            ));
+        }
+        let samples = time.as_nanos() * self.samples_in_datarecord as u128;
+        Ok(samples.div_ceil(record_nanos).min(u64::MAX as u128) as u64)
+    }
+
+    fn samples_to_time(&self, samples: u64, operation: &'static str) -> Result<Duration> {
+        if self.samples_in_datarecord <= 0 {
+            return Err(EdfError::invalid_argument(
+                self.edf.context(operation, Some(self.edfsignal)),
+                "the datarecords hold no samples of the channel",
+            ));
+        }
+        let nanos = samples as u128 * self.datarecord_duration.as_nanos()
+            / self.samples_in_datarecord as u128;
+        Ok(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+    }
+}
+
+impl Seek for SignalCursor<'_> {
+    /// Seeks to a position measured in samples.
+    ///
+    /// Positions past the end of the channel are clamped to the end.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self
+                .position()
+                .map_err(io::Error::other)?
+                .checked_add_signed(offset),
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.edf
+            .seek(self.edfsignal, SeekFrom::Start(target))
+            .map(|position| position as u64)
+            .map_err(io::Error::other)
+    }
+}
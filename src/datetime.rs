@@ -0,0 +1,178 @@
+/// A calendar date as stored in the EDF header, e.g. the birthdate of the patient.
+///
+/// With the `chrono` or `time` feature enabled it converts from and to `chrono::NaiveDate`
+/// and `time::Date` respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EDFDate {
+    pub year: i32,
+    /// 1 - 12
+    pub month: u32,
+    /// 1 - 31
+    pub day: u32,
+}
+
+impl EDFDate {
+    pub fn new(year: i32, month: u32, day: u32) -> Self {
+        Self { year, month, day }
+    }
+}
+
+/// A date and time as stored in the EDF header, e.g. the start of the recording.
+///
+/// EDF stores the start of the recording in local time without a time zone.
+/// EDF+ and BDF+ additionally store the fraction of the second with a resolution of 100 ns,
+/// finer values are truncated when writing.
+/// With the `chrono` or `time` feature enabled it converts from and to `chrono::NaiveDateTime`
+/// and `time::PrimitiveDateTime` respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EDFDateTime {
+    pub date: EDFDate,
+    /// 0 - 23
+    pub hour: u32,
+    /// 0 - 59
+    pub minute: u32,
+    /// 0 - 59
+    pub second: u32,
+    /// 0 - 999 999 999
+    pub nanosecond: u32,
+}
+
+impl EDFDateTime {
+    pub fn new(date: EDFDate, hour: u32, minute: u32, second: u32) -> Self {
+        Self {
+            date,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        }
+    }
+
+    /// Returns a copy with the fraction of the second set to `nanosecond`.
+    pub fn with_nanosecond(self, nanosecond: u32) -> Self {
+        Self { nanosecond, ..self }
+    }
+
+    /// Returns the fraction of the second in edflib's time unit (100 ns).
+    pub(crate) fn subsecond(&self) -> i32 {
+        (self.nanosecond / 100) as i32
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impl {
+    use super::*;
+    use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+
+    impl From<NaiveDate> for EDFDate {
+        fn from(date: NaiveDate) -> Self {
+            EDFDate::new(date.year(), date.month(), date.day())
+        }
+    }
+
+    impl TryFrom<EDFDate> for NaiveDate {
+        type Error = EDFDate;
+
+        /// Fails with the original value if it is not a valid date.
+        fn try_from(date: EDFDate) -> Result<Self, Self::Error> {
+            NaiveDate::from_ymd_opt(date.year, date.month, date.day).ok_or(date)
+        }
+    }
+
+    impl From<NaiveDateTime> for EDFDateTime {
+        fn from(datetime: NaiveDateTime) -> Self {
+            // chrono 用大于 999 999 999 的纳秒表示闰秒
+            EDFDateTime::new(
+                datetime.date().into(),
+                datetime.hour(),
+                datetime.minute(),
+                datetime.second(),
+            )
+            .with_nanosecond(datetime.nanosecond().min(999_999_999))
+        }
+    }
+
+    impl TryFrom<EDFDateTime> for NaiveDateTime {
+        type Error = EDFDateTime;
+
+        /// Fails with the original value if it is not a valid date and time.
+        fn try_from(datetime: EDFDateTime) -> Result<Self, Self::Error> {
+            NaiveDate::try_from(datetime.date)
+                .ok()
+                .and_then(|date| {
+                    date.and_hms_nano_opt(
+                        datetime.hour,
+                        datetime.minute,
+                        datetime.second,
+                        datetime.nanosecond,
+                    )
+                })
+                .filter(|_| datetime.nanosecond < 1_000_000_000)
+                .ok_or(datetime)
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impl {
+    use super::*;
+    use time::{Date, Month, PrimitiveDateTime, Time};
+
+    impl From<Date> for EDFDate {
+        fn from(date: Date) -> Self {
+            EDFDate::new(
+                date.year(),
+                u8::from(date.month()) as u32,
+                date.day() as u32,
+            )
+        }
+    }
+
+    impl TryFrom<EDFDate> for Date {
+        type Error = EDFDate;
+
+        /// Fails with the original value if it is not a valid date.
+        fn try_from(date: EDFDate) -> Result<Self, Self::Error> {
+            let month = u8::try_from(date.month)
+                .ok()
+                .and_then(|month| Month::try_from(month).ok())
+                .ok_or(date)?;
+            let day = u8::try_from(date.day).map_err(|_| date)?;
+            Date::from_calendar_date(date.year, month, day).map_err(|_| date)
+        }
+    }
+
+    impl From<PrimitiveDateTime> for EDFDateTime {
+        fn from(datetime: PrimitiveDateTime) -> Self {
+            EDFDateTime::new(
+                datetime.date().into(),
+                datetime.hour() as u32,
+                datetime.minute() as u32,
+                datetime.second() as u32,
+            )
+            .with_nanosecond(datetime.nanosecond())
+        }
+    }
+
+    impl TryFrom<EDFDateTime> for PrimitiveDateTime {
+        type Error = EDFDateTime;
+
+        /// Fails with the original value if it is not a valid date and time.
+        fn try_from(datetime: EDFDateTime) -> Result<Self, Self::Error> {
+            let date = Date::try_from(datetime.date).map_err(|_| datetime)?;
+            let hms = (
+                u8::try_from(datetime.hour),
+                u8::try_from(datetime.minute),
+                u8::try_from(datetime.second),
+            );
+            let time = match hms {
+                (Ok(hour), Ok(minute), Ok(second)) => {
+                    Time::from_hms_nano(hour, minute, second, datetime.nanosecond).ok()
+                }
+                _ => None,
+            }
+            .ok_or(datetime)?;
+            Ok(PrimitiveDateTime::new(date, time))
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use edflib_sys::*;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Result type used throughout this crate
+pub type Result<T, E = EdfError> = std::result::Result<T, E>;
+
+/// Describes where an error happened: the operation, the signal it was applied to and the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Name of the operation that failed, e.g. `set_label`
+    pub operation: &'static str,
+    /// Zero-based index of the signal the operation was applied to, if any
+    pub signal: Option<usize>,
+    /// Path of the file the operation was applied to
+    pub path: PathBuf,
+}
+
+impl ErrorContext {
+    pub fn new(operation: &'static str, signal: Option<usize>, path: PathBuf) -> Self {
+        Self {
+            operation,
+            signal,
+            path,
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(signal) = self.signal {
+            write!(f, " on signal {}", signal)?;
+        }
+        write!(f, " of \"{}\"", self.path.display())
+    }
+}
+
+/// Errors returned by this crate.
+///
+/// Every `EDFLIB_*` error code of edflib has its own variant, so callers can branch on the cause,
+/// e.g. `EdfError::FileAlreadyOpened { .. }` versus `EdfError::FileContainsFormatErrors { .. }`.
+/// All variants carry an [`ErrorContext`].
+#[derive(Debug, Error)]
+pub enum EdfError {
+    /// `EDFLIB_MALLOC_ERROR`
+    #[error("{context}: memory allocation error")]
+    MallocError { context: ErrorContext },
+    /// `EDFLIB_NO_SUCH_FILE_OR_DIRECTORY`
+    #[error("{context}: no such file or directory")]
+    NoSuchFileOrDirectory { context: ErrorContext },
+    /// `EDFLIB_FILE_CONTAINS_FORMAT_ERRORS`
+    #[error("{context}: file contains format errors")]
+    FileContainsFormatErrors { context: ErrorContext },
+    /// `EDFLIB_MAXFILES_REACHED`
+    #[error("{context}: too many files opened")]
+    MaxFilesReached { context: ErrorContext },
+    /// `EDFLIB_FILE_READ_ERROR`
+    #[error("{context}: a read error occurred")]
+    FileReadError { context: ErrorContext },
+    /// `EDFLIB_FILE_ALREADY_OPENED`
+    #[error("{context}: file has already been opened")]
+    FileAlreadyOpened { context: ErrorContext },
+    /// `EDFLIB_FILETYPE_ERROR`
+    #[error("{context}: wrong file type")]
+    FiletypeError { context: ErrorContext },
+    /// `EDFLIB_FILE_WRITE_ERROR`
+    #[error("{context}: a write error occurred")]
+    FileWriteError { context: ErrorContext },
+    /// `EDFLIB_NUMBER_OF_SIGNALS_INVALID`
+    #[error("{context}: invalid number of signals")]
+    NumberOfSignalsInvalid { context: ErrorContext },
+    /// `EDFLIB_FILE_IS_DISCONTINUOUS`
+    #[error("{context}: file is discontinuous and cannot be read by edflib")]
+    FileIsDiscontinuous { context: ErrorContext },
+    /// `EDFLIB_INVALID_READ_ANNOTS_VALUE`
+    #[error("{context}: invalid annotation reading mode")]
+    InvalidReadAnnotsValue { context: ErrorContext },
+    /// `EDFLIB_ARCH_ERROR`
+    #[error("{context}: architecture not supported by edflib")]
+    ArchError { context: ErrorContext },
+    /// `EDFLIB_NO_SIGNALS`
+    #[error("{context}: no signals")]
+    NoSignals { context: ErrorContext },
+    /// `EDFLIB_TOO_MANY_SIGNALS`
+    #[error("{context}: too many signals")]
+    TooManySignals { context: ErrorContext },
+    /// `EDFLIB_NO_SAMPLES_IN_RECORD`
+    #[error("{context}: a signal has no samples in a datarecord")]
+    NoSamplesInRecord { context: ErrorContext },
+    /// `EDFLIB_DIGMIN_IS_DIGMAX`
+    #[error("{context}: digital minimum equals digital maximum")]
+    DigminIsDigmax { context: ErrorContext },
+    /// `EDFLIB_DIGMAX_LOWER_THAN_DIGMIN`
+    #[error("{context}: digital maximum is lower than digital minimum")]
+    DigmaxLowerThanDigmin { context: ErrorContext },
+    /// `EDFLIB_PHYSMIN_IS_PHYSMAX`
+    #[error("{context}: physical minimum equals physical maximum")]
+    PhysminIsPhysmax { context: ErrorContext },
+    /// `EDFLIB_DATARECORD_SIZE_TOO_BIG`
+    #[error("{context}: datarecord size is too big")]
+    DatarecordSizeTooBig { context: ErrorContext },
+    /// edflib reported a failure without a specific error code (most functions return -1)
+    #[error("{context}: operation failed")]
+    Failed { context: ErrorContext },
+    /// edflib returned an error code this crate does not know about
+    #[error("{context}: unknown error code {code}")]
+    Unknown { context: ErrorContext, code: i32 },
+    /// An argument was rejected by this crate before calling edflib
+    #[error("{context}: {message}")]
+    InvalidArgument {
+        context: ErrorContext,
+        message: String,
+    },
+    /// The file has not been opened, or has already been closed
+    #[error("{context}: file is not open")]
+    NotOpen { context: ErrorContext },
+    /// More annotations were written than the annotation channels of the datarecords can hold
+    #[error("{context}: {dropped} annotations did not fit into the annotation channels and were dropped")]
+    AnnotationsDropped {
+        context: ErrorContext,
+        dropped: usize,
+    },
+    /// The thread of a `ThreadedEDFWriter` stopped after an error, which its `finish` returns
+    #[error("{context}: the writer thread has stopped")]
+    WriterThreadStopped { context: ErrorContext },
+    /// The pure-Rust parser rejected the header, `message` names the offending field.
+    ///
+    /// Corresponds to `EDFLIB_FILE_CONTAINS_FORMAT_ERRORS`.
+    #[error("{context}: invalid header, {message}")]
+    InvalidHeader {
+        context: ErrorContext,
+        message: String,
+    },
+}
+
+impl EdfError {
+    /// Decodes a negative return value of edflib.
+    ///
+    /// -1 is reported as [`EdfError::Failed`], since most functions use it as a generic error;
+    /// the functions that open files map it to [`EdfError::MallocError`] themselves.
+    pub(crate) fn from_code(code: i32, context: ErrorContext) -> Self {
+        match code {
+            EDFLIB_NO_SUCH_FILE_OR_DIRECTORY => EdfError::NoSuchFileOrDirectory { context },
+            EDFLIB_FILE_CONTAINS_FORMAT_ERRORS => EdfError::FileContainsFormatErrors { context },
+            EDFLIB_MAXFILES_REACHED => EdfError::MaxFilesReached { context },
+            EDFLIB_FILE_READ_ERROR => EdfError::FileReadError { context },
+            EDFLIB_FILE_ALREADY_OPENED => EdfError::FileAlreadyOpened { context },
+            EDFLIB_FILETYPE_ERROR => EdfError::FiletypeError { context },
+            EDFLIB_FILE_WRITE_ERROR => EdfError::FileWriteError { context },
+            EDFLIB_NUMBER_OF_SIGNALS_INVALID => EdfError::NumberOfSignalsInvalid { context },
+            EDFLIB_FILE_IS_DISCONTINUOUS => EdfError::FileIsDiscontinuous { context },
+            EDFLIB_INVALID_READ_ANNOTS_VALUE => EdfError::InvalidReadAnnotsValue { context },
+            EDFLIB_ARCH_ERROR => EdfError::ArchError { context },
+            EDFLIB_NO_SIGNALS => EdfError::NoSignals { context },
+            EDFLIB_TOO_MANY_SIGNALS => EdfError::TooManySignals { context },
+            EDFLIB_NO_SAMPLES_IN_RECORD => EdfError::NoSamplesInRecord { context },
+            EDFLIB_DIGMIN_IS_DIGMAX => EdfError::DigminIsDigmax { context },
+            EDFLIB_DIGMAX_LOWER_THAN_DIGMIN => EdfError::DigmaxLowerThanDigmin { context },
+            EDFLIB_PHYSMIN_IS_PHYSMAX => EdfError::PhysminIsPhysmax { context },
+            EDFLIB_DATARECORD_SIZE_TOO_BIG => EdfError::DatarecordSizeTooBig { context },
+            -1 => EdfError::Failed { context },
+            code => EdfError::Unknown { context, code },
+        }
+    }
+
+    pub(crate) fn invalid_argument(context: ErrorContext, message: impl Into<String>) -> Self {
+        EdfError::InvalidArgument {
+            context,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the `EDFLIB_*` error code this error corresponds to, if any.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            EdfError::MallocError { .. } => Some(EDFLIB_MALLOC_ERROR),
+            EdfError::NoSuchFileOrDirectory { .. } => Some(EDFLIB_NO_SUCH_FILE_OR_DIRECTORY),
+            EdfError::FileContainsFormatErrors { .. } => Some(EDFLIB_FILE_CONTAINS_FORMAT_ERRORS),
+            EdfError::MaxFilesReached { .. } => Some(EDFLIB_MAXFILES_REACHED),
+            EdfError::FileReadError { .. } => Some(EDFLIB_FILE_READ_ERROR),
+            EdfError::FileAlreadyOpened { .. } => Some(EDFLIB_FILE_ALREADY_OPENED),
+            EdfError::FiletypeError { .. } => Some(EDFLIB_FILETYPE_ERROR),
+            EdfError::FileWriteError { .. } => Some(EDFLIB_FILE_WRITE_ERROR),
+            EdfError::NumberOfSignalsInvalid { .. } => Some(EDFLIB_NUMBER_OF_SIGNALS_INVALID),
+            EdfError::FileIsDiscontinuous { .. } => Some(EDFLIB_FILE_IS_DISCONTINUOUS),
+            EdfError::InvalidReadAnnotsValue { .. } => Some(EDFLIB_INVALID_READ_ANNOTS_VALUE),
+            EdfError::ArchError { .. } => Some(EDFLIB_ARCH_ERROR),
+            EdfError::NoSignals { .. } => Some(EDFLIB_NO_SIGNALS),
+            EdfError::TooManySignals { .. } => Some(EDFLIB_TOO_MANY_SIGNALS),
+            EdfError::NoSamplesInRecord { .. } => Some(EDFLIB_NO_SAMPLES_IN_RECORD),
+            EdfError::DigminIsDigmax { .. } => Some(EDFLIB_DIGMIN_IS_DIGMAX),
+            EdfError::DigmaxLowerThanDigmin { .. } => Some(EDFLIB_DIGMAX_LOWER_THAN_DIGMIN),
+            EdfError::PhysminIsPhysmax { .. } => Some(EDFLIB_PHYSMIN_IS_PHYSMAX),
+            EdfError::DatarecordSizeTooBig { .. } => Some(EDFLIB_DATARECORD_SIZE_TOO_BIG),
+            EdfError::Failed { .. } => Some(-1),
+            EdfError::Unknown { code, .. } => Some(*code),
+            EdfError::InvalidHeader { .. } => Some(EDFLIB_FILE_CONTAINS_FORMAT_ERRORS),
+            EdfError::InvalidArgument { .. }
+            | EdfError::NotOpen { .. }
+            | EdfError::AnnotationsDropped { .. }
+            | EdfError::WriterThreadStopped { .. } => None,
+        }
+    }
+
+    /// Returns the context of the error.
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            EdfError::MallocError { context }
+            | EdfError::NoSuchFileOrDirectory { context }
+            | EdfError::FileContainsFormatErrors { context }
+            | EdfError::MaxFilesReached { context }
+            | EdfError::FileReadError { context }
+            | EdfError::FileAlreadyOpened { context }
+            | EdfError::FiletypeError { context }
+            | EdfError::FileWriteError { context }
+            | EdfError::NumberOfSignalsInvalid { context }
+            | EdfError::FileIsDiscontinuous { context }
+            | EdfError::InvalidReadAnnotsValue { context }
+            | EdfError::ArchError { context }
+            | EdfError::NoSignals { context }
+            | EdfError::TooManySignals { context }
+            | EdfError::NoSamplesInRecord { context }
+            | EdfError::DigminIsDigmax { context }
+            | EdfError::DigmaxLowerThanDigmin { context }
+            | EdfError::PhysminIsPhysmax { context }
+            | EdfError::DatarecordSizeTooBig { context }
+            | EdfError::Failed { context }
+            | EdfError::Unknown { context, .. }
+            | EdfError::InvalidArgument { context, .. }
+            | EdfError::NotOpen { context }
+            | EdfError::AnnotationsDropped { context, .. }
+            | EdfError::WriterThreadStopped { context }
+            | EdfError::InvalidHeader { context, .. } => context,
+        }
+    }
+
+    /// Returns the name of the operation that failed.
+    pub fn operation(&self) -> &'static str {
+        self.context().operation
+    }
+
+    /// Returns the zero-based index of the signal the failed operation was applied to, if any.
+    pub fn signal(&self) -> Option<usize> {
+        self.context().signal
+    }
+
+    /// Returns the path of the file the failed operation was applied to.
+    pub fn path(&self) -> &Path {
+        &self.context().path
+    }
+}
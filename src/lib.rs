@@ -1,15 +1,102 @@
+mod backend;
 mod base;
+mod builder;
+mod cursor;
+mod datetime;
+mod error;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "pure-rust")]
+mod native;
+#[cfg(feature = "pure-rust")]
+mod native_reader;
+#[cfg(feature = "pure-rust")]
+mod parser;
+mod reader;
+mod threaded;
 mod utils;
 mod writer;
-use crate::base::*;
 
+pub use crate::base::{
+    AnnotationPosition, Edf, FileFormat, OverlongText, ReadAnnotations, MAX_ANNOTATION_CHANNELS,
+    MAX_OPEN_FILES, MAX_WRITE_ANNOTATION_LEN,
+};
+pub use backend::Backend;
+pub use builder::*;
+pub use cursor::*;
+pub use datetime::*;
+pub use error::{EdfError, ErrorContext, Result};
+#[cfg(feature = "mmap")]
+pub use mmap::*;
+#[cfg(feature = "pure-rust")]
+pub use parser::*;
+pub use reader::*;
+pub use threaded::*;
 pub use writer::*;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::NamedTempFile;
 
+    /// Sets the minimal signal parameters edflib needs to write a valid header.
+    fn setup_signal(edf: &Edf, edfsignal: i32) {
+        edf.set_samplefrequency(edfsignal, 256).unwrap();
+        edf.set_digital_maximum(edfsignal, 32767).unwrap();
+        edf.set_digital_minimum(edfsignal, -32768).unwrap();
+        edf.set_physical_maximum(edfsignal, 1.0).unwrap();
+        edf.set_physical_minimum(edfsignal, -1.0).unwrap();
+    }
+
+    fn test_header(sample_frequency: f64) -> EDFHeader {
+        let channel = |label: &str| EDFChannel {
+            label: label.to_string(),
+            transducer: "AgAgCl cup electrodes".to_string(),
+            digital_max: 32767,
+            digital_min: -32768,
+            physical_max: 2000.0,
+            physical_min: -2000.0,
+            physical_dimension: "uV".to_string(),
+            prefilter: "HP:0.1Hz LP:75Hz N:50Hz".to_string(),
+            sample_frequency,
+        };
+        EDFHeader {
+            patient_info: EDFPatientInfo {
+                patient_name: "Demo".to_string(),
+                patient_code: "0001".to_string(),
+                sex: 1,
+                birthdate: Some(EDFDate::new(1985, 7, 12)),
+                patient_additional: "Medication".to_string(),
+                admin_code: "A01".to_string(),
+                technician: "Tech".to_string(),
+                equipment: "Device".to_string(),
+                recording_additional: "Montage".to_string(),
+            },
+            channels: vec![channel("EEG Fp1"), channel("EEG Fp2")],
+            start_datetime: Some(
+                EDFDateTime::new(EDFDate::new(2024, 3, 1), 9, 30, 15).with_nanosecond(250_000_000),
+            ),
+            datarecord_duration: None,
+            annotation_channels: None,
+            annotation_position: None,
+            file_format: None,
+        }
+    }
+
+    /// Writes `seconds` datarecords of a ramp on channel 0 and its negation on channel 1.
+    fn write_test_file(path: &std::path::Path, seconds: usize) -> EDFHeader {
+        let header = test_header(256.0);
+        let mut writer = EDFWriterBuilder::from_header(path.to_path_buf(), header.clone())
+            .open()
+            .unwrap();
+        for second in 0..seconds {
+            let ramp: Vec<f64> = (0..256).map(|i| (second * 256 + i) as f64 / 10.0).collect();
+            let negated: Vec<f64> = ramp.iter().map(|v| -v).collect();
+            writer.write_sample_stream(&[ramp, negated]).unwrap();
+        }
+        writer.finish().unwrap();
+        header
+    }
+
     #[test]
     fn test_get_version() {
         let version = Edf::get_edflib_version();
@@ -22,8 +109,8 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path.clone(), 1);
-        assert!(edf.open_file_writeonly().is_ok());
+        let edf = Edf::open_file_writeonly(path.clone(), 1);
+        assert!(edf.is_ok());
 
         // 确保文件已创建
         assert!(path.exists());
@@ -34,8 +121,8 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let mut edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
+        assert_eq!(edf.number_of_signals(), 1);
 
         assert!(edf.set_samplefrequency(0, 256).is_ok());
     }
@@ -45,8 +132,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_digital_maximum(0, 32767).is_ok());
     }
@@ -56,8 +142,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_digital_minimum(0, -32768).is_ok());
     }
@@ -67,8 +152,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_physical_dimension(0, "uV".to_string()).is_ok());
     }
@@ -78,8 +162,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_label(0, "EEG Fp1".to_string()).is_ok());
     }
@@ -89,8 +172,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_equipment("Neuroscan".to_string()).is_ok());
     }
@@ -100,8 +182,8 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path.clone(), 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        setup_signal(&edf, 0);
 
         assert!(edf
             .set_annot_chan_idx_pos(AnnotationPosition::Start)
@@ -117,8 +199,7 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
         assert!(edf.set_number_of_annotation_signals(1).is_ok());
     }
@@ -128,8 +209,8 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
+        setup_signal(&edf, 0);
 
         // 设置采样频率为256 Hz
         let sample_rate = 256.0;
@@ -154,12 +235,12 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path, 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
 
+        assert!(edf.write_annotation(0, 100, "Test Annotation").is_ok());
         assert!(edf
-            .write_annotation(0, 100, "Test Annotation".to_string())
-            .is_ok());
+            .write_annotation(0, 100, &"x".repeat(MAX_WRITE_ANNOTATION_LEN + 1))
+            .is_err());
     }
 
     #[test]
@@ -167,12 +248,1933 @@ mod tests {
         let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
         let path = temp_file.path().to_path_buf();
 
-        let edf = Edf::new(path.clone(), 1);
-        edf.open_file_writeonly().unwrap();
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        setup_signal(&edf, 0);
 
         assert!(edf.finish().is_ok());
 
         // 确保文件已正确关闭
         assert!(path.exists());
     }
+
+    #[test]
+    fn test_drop_closes_file() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        setup_signal(&edf, 0);
+        drop(edf);
+
+        // 文件已关闭，可以再次打开
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        assert_eq!(edf.path(), path);
+        assert_eq!(edf.format(), FileFormat::EdfPlus);
+    }
+
+    #[test]
+    fn test_open_files() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        assert!(!Edf::is_file_used(&path));
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        assert!(Edf::is_file_used(&path));
+        assert!((1..=MAX_OPEN_FILES).contains(&Edf::number_of_open_files()));
+        setup_signal(&edf, 0);
+        edf.finish().unwrap();
+        assert!(!Edf::is_file_used(&path));
+    }
+
+    #[test]
+    fn test_write_in_parallel() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Edf>();
+        assert_send::<OpenEDFWriter>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let path = dir.path().join(format!("recording{}.edf", i));
+                std::thread::spawn(move || {
+                    let mut writer =
+                        EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+                            .open()
+                            .unwrap();
+                    for second in 0..20 {
+                        let value = (i * 100 + second) as f64;
+                        writer
+                            .write_sample_stream(&[vec![value; 256], vec![-value; 256]])
+                            .unwrap();
+                    }
+                    writer.finish().unwrap();
+                    path
+                })
+            })
+            .collect();
+
+        for (i, thread) in threads.into_iter().enumerate() {
+            let mut reader = EDFReader::open(thread.join().unwrap()).unwrap();
+            assert_eq!(reader.datarecords_in_file(), 20);
+            let mut buf = vec![0.0; 256 * 20];
+            reader.read_physical_samples(1, &mut buf).unwrap();
+            assert!((buf[256 * 19] + (i * 100 + 19) as f64).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_reader_header() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let expected = write_test_file(&path, 3);
+
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.header.file_format, Some(FileFormat::EdfPlus));
+        let patient = &reader.header.patient_info;
+        assert_eq!(patient.patient_name, "Demo");
+        assert_eq!(patient.patient_code, "0001");
+        assert_eq!(patient.sex, 1);
+        assert_eq!(patient.admin_code, "A01");
+        assert_eq!(patient.technician, "Tech");
+        assert_eq!(patient.equipment, "Device");
+        assert_eq!(patient.patient_additional, "Medication");
+        assert_eq!(patient.recording_additional, "Montage");
+        assert_eq!(patient.birthdate, expected.patient_info.birthdate);
+        assert_eq!(reader.header.start_datetime, expected.start_datetime);
+
+        assert_eq!(reader.header.channels.len(), 2);
+        for (channel, expected) in reader.header.channels.iter().zip(&expected.channels) {
+            assert_eq!(channel.label, expected.label);
+            assert_eq!(channel.transducer, expected.transducer);
+            assert_eq!(channel.digital_max, expected.digital_max);
+            assert_eq!(channel.digital_min, expected.digital_min);
+            assert_eq!(channel.physical_max, expected.physical_max);
+            assert_eq!(channel.physical_min, expected.physical_min);
+            assert_eq!(channel.physical_dimension, expected.physical_dimension);
+            assert_eq!(channel.prefilter, expected.prefilter);
+            assert_eq!(channel.sample_frequency, expected.sample_frequency);
+        }
+
+        assert_eq!(reader.datarecords_in_file(), 3);
+        assert_eq!(
+            reader.datarecord_duration(),
+            std::time::Duration::from_secs(1)
+        );
+        assert_eq!(reader.file_duration(), std::time::Duration::from_secs(3));
+        assert_eq!(reader.samples_in_file(1), 3 * 256);
+        assert_eq!(reader.samples_in_datarecord(0), 256);
+    }
+
+    #[test]
+    fn test_writer_rejects_long_fields() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let mut header = test_header(256.0);
+        header.channels[1].prefilter = "x".repeat(81);
+
+        let err = EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header)
+            .open()
+            .err()
+            .unwrap();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.signal(), Some(1));
+    }
+
+    #[test]
+    fn test_text_fields() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        assert!(edf.set_label(0, "EEG\0Fp1".to_string()).is_err());
+        assert!(edf.set_label(0, "x".repeat(17)).is_err());
+        assert!(edf.set_patientname("张三".to_string()).is_err());
+        assert!(edf.write_annotation(0, -1, "Start\0").is_err());
+        setup_signal(&edf, 0);
+        edf.finish().unwrap();
+
+        // 截断过长的字段，Latin-1 字符由 edflib 转换为 ASCII
+        let mut header = test_header(256.0);
+        header.patient_info.patient_name = "Müller".to_string();
+        header.channels[0].label = "EEG Fp1-Referenz-Elektrode".to_string();
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .overlong_text(OverlongText::Truncate)
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+            .unwrap();
+        writer.write_annotation(0, None, &"闭眼".repeat(7)).unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open_with_annotations(path, ReadAnnotations::All).unwrap();
+        assert_eq!(reader.header.patient_info.patient_name, "Muller");
+        assert_eq!(reader.header.channels[0].label, "EEG Fp1-Referenz");
+        let annotation = reader.annotations().next().unwrap().unwrap();
+        assert_eq!(annotation.description, "闭眼".repeat(6) + "闭");
+    }
+
+    #[test]
+    fn test_write_digital_samples() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let ramp: Vec<i16> = (0..256).map(|i| i * 100 - 12800).collect();
+        let negated: Vec<i16> = ramp.iter().map(|v| -v).collect();
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+            .open()
+            .unwrap();
+        writer
+            .write_digital_sample_stream(&[ramp.clone(), negated])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        let mut buf = vec![0; 256];
+        assert_eq!(reader.read_digital_samples(0, &mut buf).unwrap(), 256);
+        let expected: Vec<i32> = ramp.iter().map(|&v| v as i32).collect();
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_write_digital_samples_bdf() {
+        let temp_file = NamedTempFile::with_suffix(".bdf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(4.0);
+        for channel in &mut header.channels {
+            channel.digital_max = 8388607;
+            channel.digital_min = -8388608;
+        }
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        let record = vec![vec![-8388608, -1, 1, 8388607], vec![0, 100000, -100000, 0]];
+        writer.write_digital_sample_stream(&record).unwrap();
+        writer.write_digital_sample_stream(&record).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 2);
+        let mut buf = vec![0; 8];
+        assert_eq!(reader.read_digital_samples(1, &mut buf).unwrap(), 8);
+        assert_eq!(buf, [0, 100000, -100000, 0, 0, 100000, -100000, 0]);
+    }
+
+    #[test]
+    fn test_write_datarecord() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(4.0);
+        header.channels[1].sample_frequency = 2.0;
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        // 两个数据记录，每个记录 4 + 2 个采样点
+        let samples = [
+            0.0, 1.0, 2.0, 3.0, -1.0, -2.0, 4.0, 5.0, 6.0, 7.0, -3.0, -4.0,
+        ];
+        writer.write_datarecord(&samples).unwrap();
+        assert!(writer.write_datarecord(&samples[..5]).is_err());
+        writer
+            .write_digital_datarecord(&[0, 0, 0, 0, 32767, -32768])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 3);
+        let mut buf = vec![0.0; 6];
+        assert_eq!(reader.read_physical_samples(1, &mut buf).unwrap(), 6);
+        for (value, expected) in buf.iter().zip([-1.0, -2.0, -3.0, -4.0, 2000.0, -2000.0]) {
+            assert!((value - expected).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_write_digital_samples_out_of_range() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let mut header = test_header(2.0);
+        header.channels[1].digital_max = 1000;
+
+        let mut writer = EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header)
+            .open()
+            .unwrap();
+        let err = writer
+            .write_digital_sample_stream(&[vec![0i16, 1], vec![0, 1001]])
+            .unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.signal(), Some(1));
+        assert!(writer
+            .write_digital_sample_stream(&[vec![0i16, 1], vec![0]])
+            .is_err());
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_reader_read_samples() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 2);
+
+        let mut reader = EDFReader::open(path).unwrap();
+        let mut physical = vec![0.0; 300];
+        assert_eq!(reader.read_physical_samples(0, &mut physical).unwrap(), 300);
+        // 16 位量化误差: 4000 uV / 65535
+        for (i, value) in physical.iter().enumerate() {
+            assert!((value - i as f64 / 10.0).abs() < 0.1);
+        }
+
+        // 每个通道有独立的读取位置
+        let mut digital = vec![0; 1000];
+        assert_eq!(reader.read_digital_samples(1, &mut digital).unwrap(), 512);
+        assert!(digital[..512].windows(2).all(|pair| pair[1] <= pair[0]));
+
+        assert_eq!(reader.read_physical_samples(0, &mut physical).unwrap(), 212);
+        assert!(reader.read_physical_samples(2, &mut physical).is_err());
+    }
+
+    #[test]
+    fn test_reader_missing_file() {
+        assert!(EDFReader::open("does/not/exist.edf".into()).is_err());
+    }
+
+    #[test]
+    fn test_cursor_seek_samples() {
+        use std::io::{Seek, SeekFrom};
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 4);
+
+        let reader = EDFReader::open(path).unwrap();
+        let mut cursor = reader.cursor(0).unwrap();
+        assert_eq!(cursor.len(), 4 * 256);
+        assert_eq!(cursor.duration(), std::time::Duration::from_secs(4));
+        assert_eq!(cursor.sample_frequency(), 256.0);
+
+        assert_eq!(cursor.seek(SeekFrom::Start(300)).unwrap(), 300);
+        let mut buf = [0.0; 10];
+        assert_eq!(cursor.read_physical(&mut buf).unwrap(), 10);
+        assert!((buf[0] - 30.0).abs() < 0.1);
+        assert_eq!(cursor.position().unwrap(), 310);
+
+        assert_eq!(cursor.seek(SeekFrom::Current(-110)).unwrap(), 200);
+        assert_eq!(cursor.seek(SeekFrom::End(-24)).unwrap(), 1000);
+        assert_eq!(cursor.seek(SeekFrom::End(100)).unwrap(), 1024);
+        assert!(cursor.seek(SeekFrom::Current(-2000)).is_err());
+
+        cursor.rewind().unwrap();
+        assert_eq!(cursor.position().unwrap(), 0);
+        assert!(reader.cursor(2).is_err());
+    }
+
+    #[test]
+    fn test_cursor_zero_durations() {
+        use std::time::Duration;
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 1);
+        // 两种读取器都拒绝这样的头信息，直接构造游标
+        let (edf, hdr) = Edf::open_file_readonly(path, ReadAnnotations::All).unwrap();
+        assert_eq!(edf.number_of_signals(), hdr.edfsignals);
+
+        let mut cursor = SignalCursor::new(&edf, 0, 256, 256, Duration::ZERO);
+        assert_eq!(cursor.duration(), Duration::ZERO);
+        assert_eq!(cursor.time().unwrap(), Duration::ZERO);
+        let err = cursor
+            .seek_time(TimeSeekFrom::Start(Duration::from_secs(1)))
+            .unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.operation(), "seek_time");
+        let err = cursor
+            .read_window(Duration::ZERO, Duration::from_secs(1))
+            .unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+
+        let mut cursor = SignalCursor::new(&edf, 0, 0, 0, Duration::from_secs(1));
+        assert_eq!(cursor.duration(), Duration::ZERO);
+        let err = cursor.time().unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.operation(), "time");
+        assert!(cursor
+            .read_window(Duration::ZERO, Duration::from_secs(1))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cursor_seek_time() {
+        use std::time::Duration;
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 4);
+
+        let reader = EDFReader::open(path).unwrap();
+        let mut cursor = reader.cursor(1).unwrap();
+        let pos = cursor
+            .seek_time(TimeSeekFrom::Start(Duration::from_millis(1500)))
+            .unwrap();
+        assert_eq!(pos, 384);
+        assert_eq!(cursor.time().unwrap(), Duration::from_millis(1500));
+        assert_eq!(
+            cursor
+                .seek_time(TimeSeekFrom::Forward(Duration::from_millis(500)))
+                .unwrap(),
+            512
+        );
+        assert_eq!(
+            cursor
+                .seek_time(TimeSeekFrom::Backward(Duration::from_secs(1)))
+                .unwrap(),
+            256
+        );
+        assert_eq!(
+            cursor
+                .seek_time(TimeSeekFrom::End(Duration::from_secs(1)))
+                .unwrap(),
+            768
+        );
+        assert!(cursor
+            .seek_time(TimeSeekFrom::Backward(Duration::from_secs(5)))
+            .is_err());
+
+        // 不同通道的读取位置互不影响
+        let mut other = reader.cursor(0).unwrap();
+        assert_eq!(other.position().unwrap(), 0);
+
+        let window = other
+            .read_window(Duration::from_secs(1), Duration::from_millis(250))
+            .unwrap();
+        assert_eq!(window.len(), 64);
+        assert!((window[0] - 25.6).abs() < 0.1);
+        assert_eq!(other.position().unwrap(), 320);
+
+        let tail = other
+            .read_window(Duration::from_millis(3900), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(tail.len(), 1024 - 999);
+
+        // 窗口的结束时间溢出时返回错误，位置不变
+        let err = other
+            .read_window(Duration::from_secs(1), Duration::MAX)
+            .unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.operation(), "read_window");
+        assert_eq!(other.position().unwrap(), 1024);
+    }
+
+    #[test]
+    fn test_reader_annotations() {
+        use std::time::Duration;
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+            .open()
+            .unwrap();
+        for _ in 0..3 {
+            writer
+                .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+                .unwrap();
+        }
+        writer.write_annotation(0, None, "Start").unwrap();
+        writer
+            .write_annotation(1_500_000, Some(250_000), "Eyes closed")
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open_with_annotations(path.clone(), ReadAnnotations::All).unwrap();
+        assert_eq!(reader.annotations_in_file(), 2);
+        let annotations: Vec<EDFAnnotation> =
+            reader.annotations().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            annotations,
+            vec![
+                EDFAnnotation {
+                    onset: Duration::ZERO,
+                    duration: None,
+                    description: "Start".to_string(),
+                },
+                EDFAnnotation {
+                    onset: Duration::from_millis(1500),
+                    duration: Some(Duration::from_millis(250)),
+                    description: "Eyes closed".to_string(),
+                },
+            ]
+        );
+        assert_eq!(reader.annotations().len(), 2);
+        drop(reader);
+
+        // UTF-8 描述和超过 35 分钟的起始时间
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+            .unwrap();
+        let annotation = EDFAnnotation {
+            onset: Duration::from_secs(3 * 3600) + Duration::from_micros(7),
+            duration: Some(Duration::from_secs(90)),
+            description: "Ärger 闭眼".to_string(),
+        };
+        writer.write_edf_annotation(&annotation).unwrap();
+        let too_long = EDFAnnotation {
+            description: "闭眼".repeat(7),
+            ..annotation.clone()
+        };
+        assert!(writer.write_edf_annotation(&too_long).is_err());
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open_with_annotations(path.clone(), ReadAnnotations::All).unwrap();
+        let annotations: Vec<EDFAnnotation> =
+            reader.annotations().collect::<Result<_, _>>().unwrap();
+        assert_eq!(annotations, vec![annotation]);
+        drop(reader);
+
+        let reader = EDFReader::open_with_annotations(path, ReadAnnotations::None).unwrap();
+        assert_eq!(reader.annotations().count(), 0);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let err = EDFReader::open("does/not/exist.edf".into()).err().unwrap();
+        assert!(matches!(err, EdfError::NoSuchFileOrDirectory { .. }));
+        assert_eq!(
+            err.code(),
+            Some(edflib_sys::EDFLIB_NO_SUCH_FILE_OR_DIRECTORY)
+        );
+        assert_eq!(err.operation(), "open_file_readonly");
+        assert_eq!(err.path(), std::path::Path::new("does/not/exist.edf"));
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let edf = Edf::open_file_writeonly(path.clone(), 1).unwrap();
+        let err = Edf::open_file_writeonly(path.clone(), 1).err().unwrap();
+        assert!(matches!(err, EdfError::FileAlreadyOpened { .. }));
+
+        let err = edf.set_samplefrequency(3, 256).err().unwrap();
+        assert!(matches!(err, EdfError::Failed { .. }));
+        assert_eq!(err.signal(), Some(3));
+        assert_eq!(err.operation(), "set_samplefrequency");
+
+        setup_signal(&edf, 0);
+        edf.set_digital_minimum(0, 40000).unwrap();
+        let err = edf.finish().err().unwrap();
+        assert!(matches!(err, EdfError::DigmaxLowerThanDigmin { .. }));
+        assert!(err
+            .to_string()
+            .contains("digital maximum is lower than digital minimum"));
+    }
+
+    #[test]
+    fn test_write_multi_frames_rejects_invalid_frames() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+            .open()
+            .unwrap();
+        let frame = || vec![vec![1.0; 256], vec![-1.0; 256]];
+
+        let mut with_nan = frame();
+        with_nan[1][100] = f64::NAN;
+        let err = writer.write_multi_frames(&[frame(), with_nan]).unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.operation(), "write_multi_frames");
+        assert_eq!(err.signal(), Some(1));
+
+        let mut short = frame();
+        short[0].truncate(200);
+        let err = writer.write_multi_frames(&[frame(), short]).unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.signal(), Some(0));
+
+        // 出错时不写入任何帧
+        writer.write_multi_frames(&[frame(), frame()]).unwrap();
+        writer.finish().unwrap();
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 2);
+    }
+
+    #[test]
+    fn test_write_rejects_nan() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .open()
+            .unwrap();
+
+        // push 在缓冲之前检查，已缓冲的采样点不受影响
+        writer.push(0, &[1.0; 10]).unwrap();
+        let err = writer.push(1, &[1.0, f64::NAN]).unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.operation(), "push");
+        assert_eq!(err.signal(), Some(1));
+        assert_eq!(writer.pending_samples(0), 10);
+        assert_eq!(writer.pending_samples(1), 0);
+        writer.push(0, &[1.0; 6]).unwrap();
+        writer.push(1, &[1.0; 16]).unwrap();
+
+        let mut frame = vec![vec![0.0; 16], vec![0.0; 16]];
+        frame[1][3] = f64::NAN;
+        let err = writer.write_sample_stream(&frame).unwrap_err();
+        assert_eq!(err.operation(), "write_sample_stream");
+        assert_eq!(err.signal(), Some(1));
+
+        let mut record = vec![0.0; 64];
+        record[40] = f64::NAN;
+        let err = writer.write_datarecord(&record).unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.signal(), Some(0));
+
+        writer.finish().unwrap();
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 1);
+    }
+
+    #[test]
+    fn test_write_mixed_sample_rates() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(512.0);
+        header.channels[1].sample_frequency = 1.0;
+        header.channels.push(EDFChannel {
+            label: "Accel X".to_string(),
+            sample_frequency: 32.0,
+            ..header.channels[0].clone()
+        });
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        assert_eq!(
+            writer.datarecord_duration(),
+            std::time::Duration::from_secs(1)
+        );
+        let frame = vec![vec![0.0; 512], vec![1.0], vec![2.0; 32]];
+        writer.write_sample_stream(&frame).unwrap();
+        writer.write_sample_stream(&frame).unwrap();
+        let err = writer
+            .write_sample_stream(&[vec![0.0; 512], vec![1.0; 2], vec![2.0; 32]])
+            .unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+        assert_eq!(err.signal(), Some(1));
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 2);
+        assert_eq!(reader.samples_in_datarecord(0), 512);
+        assert_eq!(reader.samples_in_datarecord(1), 1);
+        assert_eq!(reader.samples_in_datarecord(2), 32);
+        assert_eq!(reader.header.channels[2].sample_frequency, 32.0);
+    }
+
+    #[test]
+    fn test_write_fractional_sample_rate() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(12.5);
+        header.channels[1].sample_frequency = 0.2;
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        // 12.5 Hz 和 0.2 Hz 都需要 10 秒的数据记录才能有整数个采样点
+        assert_eq!(
+            writer.datarecord_duration(),
+            std::time::Duration::from_secs(10)
+        );
+        assert_eq!(writer.samples_in_datarecord(0), 125);
+        assert_eq!(writer.samples_in_datarecord(1), 2);
+        assert!(writer
+            .write_sample_stream(&[vec![0.0; 12], vec![0.0]])
+            .is_err());
+        writer.finish().unwrap();
+
+        let mut header = test_header(12.5);
+        header.channels[1].sample_frequency = 0.5;
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        assert_eq!(
+            writer.datarecord_duration(),
+            std::time::Duration::from_secs(2)
+        );
+        writer
+            .write_sample_stream(&[vec![0.0; 25], vec![0.0]])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(
+            reader.datarecord_duration(),
+            std::time::Duration::from_secs(2)
+        );
+        assert_eq!(reader.header.channels[0].sample_frequency, 12.5);
+        assert_eq!(reader.header.channels[1].sample_frequency, 0.5);
+
+        let mut header = test_header(256.0);
+        header.channels[1].sample_frequency = 0.0;
+        assert!(matches!(
+            EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header)
+                .open()
+                .err()
+                .unwrap(),
+            EdfError::InvalidArgument { .. }
+        ));
+    }
+
+    #[test]
+    fn test_datarecord_duration() {
+        use std::time::Duration;
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(256.0);
+        header.datarecord_duration = Some(Duration::from_millis(1500));
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        assert_eq!(writer.samples_in_datarecord(0), 384);
+        writer
+            .write_sample_stream(&[vec![0.0; 384], vec![0.0; 384]])
+            .unwrap();
+        writer.finish().unwrap();
+        let reader = EDFReader::open(path.clone()).unwrap();
+        assert_eq!(reader.datarecord_duration(), Duration::from_millis(1500));
+        assert_eq!(reader.header.channels[0].sample_frequency, 256.0);
+        drop(reader);
+
+        // 短于 10 ms 的数据记录使用微秒分辨率
+        let mut header = test_header(20_000.0);
+        header.datarecord_duration = Some(Duration::from_micros(250));
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        assert_eq!(writer.samples_in_datarecord(1), 5);
+        writer
+            .write_sample_stream(&[vec![0.0; 5], vec![0.0; 5]])
+            .unwrap();
+        writer.finish().unwrap();
+        let reader = EDFReader::open(path.clone()).unwrap();
+        assert_eq!(reader.datarecord_duration(), Duration::from_micros(250));
+        assert_eq!(
+            reader.header.datarecord_duration,
+            Some(Duration::from_micros(250))
+        );
+        drop(reader);
+
+        for duration in [
+            Duration::from_millis(300),
+            Duration::from_nanos(1_500_500),
+            Duration::from_secs(61),
+        ] {
+            let mut header = test_header(256.0);
+            header.datarecord_duration = Some(duration);
+            assert!(matches!(
+                EDFWriterBuilder::from_header(path.clone(), header)
+                    .open()
+                    .err()
+                    .unwrap(),
+                EdfError::InvalidArgument { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_file_format() {
+        let temp_file = NamedTempFile::with_suffix(".dat").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(4.0);
+        header.file_format = Some(FileFormat::BdfPlus);
+        for channel in &mut header.channels {
+            channel.digital_max = 8388607;
+            channel.digital_min = -8388608;
+        }
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header.clone())
+            .open()
+            .unwrap();
+        writer
+            .write_digital_sample_stream(&[vec![8388607; 4], vec![-8388608; 4]])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.header.file_format, Some(FileFormat::BdfPlus));
+        let mut buf = vec![0; 4];
+        reader.read_digital_samples(0, &mut buf).unwrap();
+        assert_eq!(buf, [8388607; 4]);
+
+        // 扩展名与格式不一致
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        assert!(matches!(
+            EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header.clone())
+                .open()
+                .err()
+                .unwrap(),
+            EdfError::InvalidArgument { .. }
+        ));
+
+        // edflib 不能写入标准 EDF/BDF，需要 pure-rust 功能
+        #[cfg(not(feature = "pure-rust"))]
+        {
+            header.file_format = Some(FileFormat::Edf);
+            assert!(matches!(
+                EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header)
+                    .open()
+                    .err()
+                    .unwrap(),
+                EdfError::InvalidArgument { .. }
+            ));
+        }
+        assert!(matches!(
+            Edf::open_file_writeonly_with_format(
+                temp_file.path().to_path_buf(),
+                FileFormat::Edf,
+                1
+            )
+            .err()
+            .unwrap(),
+            EdfError::FiletypeError { .. }
+        ));
+    }
+
+    #[test]
+    fn test_push_irregular_packets() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(64.0);
+        header.channels[1].sample_frequency = 32.0;
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .final_record_policy(FinalRecordPolicy::PadWithLastSample)
+            .open()
+            .unwrap();
+        let ramp: Vec<f64> = (0..150).map(|i| i as f64).collect();
+        for packet in ramp.chunks(17) {
+            writer.push(0, packet).unwrap();
+        }
+        assert_eq!(writer.pending_samples(0), 150);
+        writer.push(1, &[1.0; 40]).unwrap();
+        // 第一个数据记录已写入
+        assert_eq!(writer.pending_samples(0), 150 - 64);
+        assert_eq!(writer.pending_samples(1), 8);
+        assert!(writer
+            .write_sample_stream(&[vec![0.0; 64], vec![0.0; 32]])
+            .is_err());
+        assert!(writer.push(2, &[0.0]).is_err());
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 3);
+        let mut buf = vec![0.0; 192];
+        assert_eq!(reader.read_physical_samples(0, &mut buf).unwrap(), 192);
+        for (i, value) in buf.iter().enumerate() {
+            let expected = i.min(149) as f64;
+            assert!((value - expected).abs() < 0.1, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn test_push_final_record_policy() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .final_record_policy(FinalRecordPolicy::Discard)
+            .open()
+            .unwrap();
+        writer.push(0, &[1.0; 20]).unwrap();
+        writer.push(1, &[1.0; 20]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            EDFReader::open(path.clone()).unwrap().datarecords_in_file(),
+            1
+        );
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .open()
+            .unwrap();
+        writer.push(0, &[1.0; 20]).unwrap();
+        writer.push(1, &[1.0; 10]).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 2);
+        let mut buf = vec![0.0; 32];
+        reader.read_physical_samples(1, &mut buf).unwrap();
+        assert!(buf[..10].iter().all(|value| (value - 1.0).abs() < 0.1));
+        assert!(buf[10..].iter().all(|value| value.abs() < 0.1));
+    }
+
+    #[test]
+    fn test_annotation_channels() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        // 每个数据记录可容纳 3 个注释，放在信号之前
+        let mut header = test_header(16.0);
+        header.annotation_channels = Some(3);
+        header.annotation_position = Some(AnnotationPosition::Start);
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        for i in 0..6 {
+            writer
+                .write_annotation(i * 1000, None, &format!("Event {}", i))
+                .unwrap();
+        }
+        for _ in 0..2 {
+            writer
+                .write_sample_stream(&[vec![0.0; 16], vec![0.0; 16]])
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[256..272], b"EDF Annotations ");
+        let reader = EDFReader::open_with_annotations(path.clone(), ReadAnnotations::All).unwrap();
+        assert_eq!(reader.header.channels.len(), 2);
+        assert_eq!(reader.annotations_in_file(), 6);
+        drop(reader);
+
+        // 自动模式按第一个数据记录之前写入的注释数量分配
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .open()
+            .unwrap();
+        for i in 0..5 {
+            writer.write_annotation(i * 1000, None, "Event").unwrap();
+        }
+        writer.push(0, &[0.0; 16]).unwrap();
+        writer.push(1, &[0.0; 16]).unwrap();
+        writer.finish().unwrap();
+        let reader = EDFReader::open_with_annotations(path.clone(), ReadAnnotations::All).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 1);
+        assert_eq!(reader.annotations_in_file(), 5);
+        drop(reader);
+
+        // 放不下的注释会报告出来
+        let mut header = test_header(16.0);
+        header.annotation_channels = Some(1);
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 16], vec![0.0; 16]])
+            .unwrap();
+        for i in 0..3 {
+            writer.write_annotation(i * 1000, None, "Event").unwrap();
+        }
+        let err = writer.finish().unwrap_err();
+        assert!(matches!(
+            err,
+            EdfError::AnnotationsDropped { dropped: 2, .. }
+        ));
+        assert_eq!(err.code(), None);
+
+        let mut header = test_header(16.0);
+        header.annotation_channels = Some(MAX_ANNOTATION_CHANNELS + 1);
+        assert!(matches!(
+            EDFWriterBuilder::from_header(path, header)
+                .open()
+                .err()
+                .unwrap(),
+            EdfError::InvalidArgument { .. }
+        ));
+    }
+
+    #[test]
+    fn test_automatic_annotation_channels() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        // 先写样本再写注释，通道数按全部注释分配
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .open()
+            .unwrap();
+        for _ in 0..2 {
+            writer
+                .write_sample_stream(&[vec![0.0; 16], vec![0.0; 16]])
+                .unwrap();
+        }
+        for i in 0..5 {
+            writer
+                .write_annotation(i * 1000, None, &format!("Event {}", i))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let reader = EDFReader::open_with_annotations(path.clone(), ReadAnnotations::All).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 2);
+        let descriptions: Vec<_> = reader
+            .annotations()
+            .map(|annotation| annotation.unwrap().description)
+            .collect();
+        assert_eq!(
+            descriptions,
+            (0..5).map(|i| format!("Event {}", i)).collect::<Vec<_>>()
+        );
+        drop(reader);
+
+        // 只有注释时 edflib 为每个注释写入一个数据记录
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(16.0))
+            .open()
+            .unwrap();
+        for i in 0..3 {
+            writer.write_annotation(i * 1000, None, "Event").unwrap();
+        }
+        writer.finish().unwrap();
+        let reader = EDFReader::open_with_annotations(path, ReadAnnotations::All).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 3);
+        assert_eq!(reader.annotations_in_file(), 3);
+    }
+
+    #[test]
+    fn test_writer_builder() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let header = test_header(128.0);
+
+        let mut writer = EDFWriterBuilder::new(path.clone())
+            .channels(header.channels.clone())
+            .start_datetime(EDFDateTime::new(EDFDate::new(2024, 3, 1), 9, 30, 15))
+            .final_record_policy(FinalRecordPolicy::Discard)
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 128], vec![0.0; 128]])
+            .unwrap();
+        writer.push(0, &[0.0; 100]).unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open(path.clone()).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 1);
+        assert_eq!(reader.header.channels.len(), 2);
+        assert_eq!(reader.header.patient_info.sex, -1);
+        drop(reader);
+
+        // 在创建文件之前检查每一项设置
+        let invalid: [fn(&mut EDFChannel); 6] = [
+            |ch| ch.digital_min = ch.digital_max,
+            |ch| ch.physical_min = ch.physical_max,
+            |ch| ch.label = "x".repeat(17),
+            |ch| ch.physical_dimension = "x".repeat(9),
+            |ch| ch.sample_frequency = 0.0,
+            |ch| ch.sample_frequency = f64::NAN,
+        ];
+        let missing = temp_file.path().with_extension("new.edf");
+        for change in invalid {
+            let mut channel = header.channels[0].clone();
+            change(&mut channel);
+            let builder =
+                EDFWriterBuilder::from_header(missing.clone(), header.clone()).channel(channel);
+            let err = builder.validate().unwrap_err();
+            assert!(matches!(err, EdfError::InvalidArgument { .. }));
+            assert_eq!(err.signal(), Some(2));
+            assert!(builder.open().is_err());
+            assert!(!missing.exists());
+        }
+        assert!(EDFWriterBuilder::new(missing.clone()).validate().is_err());
+    }
+
+    #[test]
+    fn test_threaded_writer() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ThreadedEDFWriter>();
+
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+            .open()
+            .unwrap();
+
+        // 队列只能容纳一个数据帧，生产者会被阻塞直到写入线程跟上
+        let writer = ThreadedEDFWriter::spawn(writer, 1);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..10 {
+                    writer
+                        .write_frame(vec![vec![1.0; 256], vec![-1.0; 256]])
+                        .unwrap();
+                }
+            });
+        });
+        writer.push(0, vec![2.0; 256]).unwrap();
+        writer.push(1, vec![-2.0; 256]).unwrap();
+        writer
+            .write_annotation(EDFAnnotation {
+                onset: std::time::Duration::from_secs(5),
+                duration: None,
+                description: "Queued".to_string(),
+            })
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = EDFReader::open(path.clone()).unwrap();
+        assert_eq!(reader.datarecords_in_file(), 11);
+        assert_eq!(reader.annotations_in_file(), 1);
+        let mut buf = vec![0.0; 256 * 11];
+        reader.read_physical_samples(0, &mut buf).unwrap();
+        assert!((buf[0] - 1.0).abs() < 0.1);
+        assert!((buf[256 * 10] - 2.0).abs() < 0.1);
+        drop(reader);
+
+        // 写入线程的错误由 finish 返回
+        let writer = EDFWriterBuilder::from_header(path, test_header(256.0))
+            .open()
+            .unwrap();
+        let writer = ThreadedEDFWriter::spawn(writer, 4);
+        writer.write_frame(vec![vec![0.0; 256]]).unwrap();
+        let err = writer.finish().unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+    }
+
+    #[test]
+    fn test_writer_not_open() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let mut writer =
+            EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), test_header(256.0))
+                .open()
+                .unwrap();
+        writer.finish().unwrap();
+        // 文件关闭后不能再写入
+        let err = writer
+            .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+            .err()
+            .unwrap();
+        assert!(matches!(err, EdfError::NotOpen { .. }));
+        assert_eq!(err.code(), None);
+    }
+
+    #[test]
+    fn test_set_birthdate_and_startdatetime() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+
+        let edf = Edf::open_file_writeonly(path, 1).unwrap();
+
+        assert!(edf.set_birthdate(EDFDate::new(1970, 1, 31)).is_ok());
+        assert!(edf.set_birthdate(EDFDate::new(1970, 13, 1)).is_err());
+        let start = EDFDateTime::new(EDFDate::new(2020, 2, 29), 23, 59, 59);
+        assert!(edf.set_startdatetime(start).is_ok());
+        let start = EDFDateTime::new(EDFDate::new(1980, 1, 1), 0, 0, 0);
+        assert!(edf.set_startdatetime(start).is_err());
+
+        assert!(edf.set_subsecond_starttime(9_999_999).is_ok());
+        assert!(edf.set_subsecond_starttime(10_000_000).is_err());
+    }
+
+    #[test]
+    fn test_subsecond_starttime() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(256.0);
+        let start = EDFDateTime::new(EDFDate::new(2024, 3, 1), 9, 30, 15);
+        // 分辨率为 100 ns，多余的部分被截断
+        header.start_datetime = Some(start.with_nanosecond(123_456_789));
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(
+            reader.header.start_datetime,
+            Some(start.with_nanosecond(123_456_700))
+        );
+    }
+
+    #[test]
+    fn test_writer_without_dates() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        let mut header = test_header(256.0);
+        header.patient_info.birthdate = None;
+        header.start_datetime = None;
+
+        let mut writer = EDFWriterBuilder::from_header(path.clone(), header)
+            .open()
+            .unwrap();
+        writer
+            .write_sample_stream(&[vec![0.0; 256], vec![0.0; 256]])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let reader = EDFReader::open(path).unwrap();
+        assert_eq!(reader.header.patient_info.birthdate, None);
+        // 未设置开始时间时 edflib 使用当前系统时间
+        assert!(reader.header.start_datetime.unwrap().date.year >= 2024);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_conversion() {
+        use chrono::{NaiveDate, NaiveDateTime};
+
+        let datetime = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_micro_opt(9, 30, 15, 500)
+            .unwrap();
+        let edf_datetime = EDFDateTime::from(datetime);
+        assert_eq!(
+            edf_datetime,
+            EDFDateTime::new(EDFDate::new(2024, 3, 1), 9, 30, 15).with_nanosecond(500_000)
+        );
+        assert_eq!(NaiveDateTime::try_from(edf_datetime), Ok(datetime));
+        assert!(NaiveDate::try_from(EDFDate::new(2023, 2, 29)).is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time_conversion() {
+        use time::{Date, Month, PrimitiveDateTime, Time};
+
+        let datetime = PrimitiveDateTime::new(
+            Date::from_calendar_date(2024, Month::March, 1).unwrap(),
+            Time::from_hms_micro(9, 30, 15, 500).unwrap(),
+        );
+        let edf_datetime = EDFDateTime::from(datetime);
+        assert_eq!(
+            edf_datetime,
+            EDFDateTime::new(EDFDate::new(2024, 3, 1), 9, 30, 15).with_nanosecond(500_000)
+        );
+        assert_eq!(PrimitiveDateTime::try_from(edf_datetime), Ok(datetime));
+        assert!(Date::try_from(EDFDate::new(2023, 2, 29)).is_err());
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn assert_same_header(parsed: &EDFHeader, read: &EDFHeader) {
+        let (a, b) = (&parsed.patient_info, &read.patient_info);
+        assert_eq!(a.patient_name, b.patient_name);
+        assert_eq!(a.patient_code, b.patient_code);
+        assert_eq!(a.sex, b.sex);
+        assert_eq!(a.birthdate, b.birthdate);
+        assert_eq!(a.patient_additional, b.patient_additional);
+        assert_eq!(a.admin_code, b.admin_code);
+        assert_eq!(a.technician, b.technician);
+        assert_eq!(a.equipment, b.equipment);
+        assert_eq!(a.recording_additional, b.recording_additional);
+
+        assert_eq!(parsed.channels.len(), read.channels.len());
+        for (a, b) in parsed.channels.iter().zip(&read.channels) {
+            assert_eq!(a.label, b.label);
+            assert_eq!(a.transducer, b.transducer);
+            assert_eq!(a.digital_max, b.digital_max);
+            assert_eq!(a.digital_min, b.digital_min);
+            assert_eq!(a.physical_max, b.physical_max);
+            assert_eq!(a.physical_min, b.physical_min);
+            assert_eq!(a.physical_dimension, b.physical_dimension);
+            assert_eq!(a.prefilter, b.prefilter);
+            assert_eq!(a.sample_frequency, b.sample_frequency);
+        }
+
+        assert_eq!(parsed.start_datetime, read.start_datetime);
+        assert_eq!(parsed.datarecord_duration, read.datarecord_duration);
+        assert_eq!(parsed.file_format, read.file_format);
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_parser_matches_reader() {
+        for suffix in [".edf", ".bdf"] {
+            let temp_file = NamedTempFile::with_suffix(suffix).unwrap();
+            let path = temp_file.path().to_path_buf();
+            let mut writer = EDFWriterBuilder::from_header(path.clone(), test_header(256.0))
+                .open()
+                .unwrap();
+            for _ in 0..3 {
+                writer
+                    .write_sample_stream(&[vec![0.5; 256], vec![-0.5; 256]])
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+
+            let reader = EDFReader::open(path.clone()).unwrap();
+            let parsed = EDFParsedHeader::open(path.clone()).unwrap();
+            assert_same_header(&parsed.header, &reader.header);
+            assert_eq!(parsed.datarecords_in_file, reader.datarecords_in_file());
+            assert_eq!(parsed.datarecord_duration, reader.datarecord_duration());
+            assert!(!parsed.discontinuous);
+            // 两个信号加一个注释信号
+            assert_eq!(parsed.signals.len(), 3);
+            assert_eq!(parsed.signals.iter().filter(|s| s.annotation).count(), 1);
+            assert_eq!(parsed.header_len, 4 * 256);
+
+            let bytes = std::fs::read(&path).unwrap();
+            let from_bytes = EDFParsedHeader::from_bytes(&bytes).unwrap();
+            assert_same_header(&from_bytes.header, &reader.header);
+            assert_eq!(
+                bytes.len(),
+                from_bytes.header_len + from_bytes.datarecord_len * 3
+            );
+        }
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_parser_plain_edf() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 2);
+
+        // 清空保留字段得到普通 EDF，注释信号变成名为 "EDF_Annotations" 的普通信号
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[192..197].copy_from_slice(b"     ");
+        std::fs::write(&path, &bytes).unwrap();
+
+        let reader = EDFReader::open(path.clone()).unwrap();
+        let parsed = EDFParsedHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.format, FileFormat::Edf);
+        assert_same_header(&parsed.header, &reader.header);
+        assert_eq!(parsed.header.channels[2].label, "EDF_Annotations");
+        assert!(parsed
+            .header
+            .patient_info
+            .patient_name
+            .starts_with("0001 M 12-JUL-1985"));
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_parser_rejects_malformed_headers() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 1);
+        let bytes = std::fs::read(&path).unwrap();
+
+        let parse = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            change(&mut bytes);
+            // edflib 也必须拒绝同样的文件
+            std::fs::write(&path, &bytes).unwrap();
+            assert!(EDFReader::open(path.clone()).is_err());
+            EDFParsedHeader::from_bytes(&bytes).err().unwrap()
+        };
+
+        let err = parse(&|bytes| bytes[170] = b'/');
+        assert!(matches!(err, EdfError::InvalidHeader { .. }));
+        assert_eq!(
+            err.code(),
+            Some(edflib_sys::EDFLIB_FILE_CONTAINS_FORMAT_ERRORS)
+        );
+        assert_eq!(err.operation(), "from_bytes");
+
+        // 第二个信号的物理最大值 (physical maximum)
+        let err = parse(&|bytes| bytes[256 + 3 * 112 + 8] = b'x');
+        assert_eq!(err.signal(), Some(1));
+
+        let err = parse(&|bytes| bytes.truncate(bytes.len() - 1));
+        assert!(matches!(err, EdfError::InvalidHeader { .. }));
+
+        let err = parse(&|bytes| bytes.truncate(100));
+        assert!(matches!(err, EdfError::FileReadError { .. }));
+
+        let err = EDFParsedHeader::open("does-not-exist.edf".into()).unwrap_err();
+        assert!(matches!(err, EdfError::NoSuchFileOrDirectory { .. }));
+    }
+
+    /// Writes a file with `backend` and returns its contents.
+    #[cfg(feature = "pure-rust")]
+    fn write_with_backend(
+        suffix: &str,
+        header: &EDFHeader,
+        backend: Backend,
+        write: &dyn Fn(&mut OpenEDFWriter) -> Result<()>,
+    ) -> Vec<u8> {
+        let temp_file = NamedTempFile::with_suffix(suffix).unwrap();
+        let mut writer =
+            EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header.clone())
+                .backend(backend)
+                .overlong_text(OverlongText::Truncate)
+                .open()
+                .unwrap();
+        write(&mut writer).unwrap();
+        writer.finish().unwrap();
+        std::fs::read(temp_file.path()).unwrap()
+    }
+
+    /// Asserts that edflib and the pure-Rust backend write the same file.
+    #[cfg(feature = "pure-rust")]
+    fn assert_backends_match(
+        suffix: &str,
+        header: &EDFHeader,
+        write: &dyn Fn(&mut OpenEDFWriter) -> Result<()>,
+    ) {
+        let expected = write_with_backend(suffix, header, Backend::Edflib, write);
+        let actual = write_with_backend(suffix, header, Backend::PureRust, write);
+        assert_eq!(actual.len(), expected.len());
+        if let Some(pos) = actual.iter().zip(&expected).position(|(a, b)| a != b) {
+            panic!(
+                "files differ at byte {}: {:?} != {:?}",
+                pos,
+                String::from_utf8_lossy(&actual[pos..(pos + 16).min(actual.len())]),
+                String::from_utf8_lossy(&expected[pos..(pos + 16).min(expected.len())])
+            );
+        }
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_native_writer_matches_generator_example() {
+        // 与 examples/generator.rs 相同的文件
+        let channel = |label: &str| EDFChannel {
+            label: label.to_string(),
+            transducer: "AgAgCl cup electrodes".to_string(),
+            digital_max: 32767,
+            digital_min: -32768,
+            physical_max: 2000.0,
+            physical_min: -2000.0,
+            physical_dimension: "mV".to_string(),
+            prefilter: "HP:0.1Hz LP:75Hz".to_string(),
+            sample_frequency: 256.0,
+        };
+        let header = EDFHeader {
+            patient_info: EDFPatientInfo {
+                patient_name: "Demo".to_string(),
+                patient_code: "0001".to_string(),
+                sex: 0,
+                birthdate: Some(EDFDate::new(1990, 5, 17)),
+                admin_code: "0001".to_string(),
+                technician: "DYZS".to_string(),
+                equipment: "DYZS".to_string(),
+                ..EDFPatientInfo::default()
+            },
+            channels: vec![channel("Sine20Hz"), channel("Sine50Hz")],
+            start_datetime: Some(EDFDateTime::new(EDFDate::new(2024, 12, 27), 10, 0, 0)),
+            datarecord_duration: None,
+            annotation_channels: None,
+            annotation_position: None,
+            file_format: None,
+        };
+
+        assert_backends_match(".bdf", &header, &|writer| {
+            let sine = |frequency: f64| -> Vec<f64> {
+                (0..256)
+                    .map(|i| {
+                        (2.0 * std::f64::consts::PI * frequency * i as f64 / 256.0).sin() * 1000.0
+                    })
+                    .collect()
+            };
+            let frames = vec![vec![sine(20.0), sine(50.0)]; 10];
+            writer.write_multi_frames(&frames)?;
+            writer.write_annotation(0, Some(0), "Start of recording")?;
+            writer.write_annotation(10_000_000, Some(0), "End of recording")
+        });
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_native_writer_matches_edflib() {
+        let mut header = test_header(256.0);
+        header.patient_info.patient_name = "Zoë Ünal-Smith".to_string();
+        header.patient_info.patient_additional = "x".repeat(80);
+        header.patient_info.recording_additional = "  Montage 10-20  ".to_string();
+        header.channels[0].label = "EEG Fp1 with a long label".to_string();
+        header.channels[1].physical_min = -0.123456789;
+        header.channels[1].physical_max = 1234.5;
+        header.channels[1].digital_min = -1000;
+        header.channels[1].digital_max = 1000;
+        header.channels.push(EDFChannel {
+            label: "Resp".to_string(),
+            sample_frequency: 32.0,
+            ..header.channels[1].clone()
+        });
+        header.datarecord_duration = Some(std::time::Duration::from_millis(250));
+        header.annotation_channels = Some(2);
+        header.annotation_position = Some(AnnotationPosition::Middle);
+
+        let write = |writer: &mut OpenEDFWriter| -> Result<()> {
+            let ramp = |len: usize, scale: f64| -> Vec<f64> {
+                (0..len).map(|i| (i as f64 - 20.0) * scale).collect()
+            };
+            for second in 0..3 {
+                let scale = 37.3 * (second + 1) as f64;
+                writer.write_sample_stream(&[ramp(64, scale), ramp(64, -scale), ramp(8, 0.1)])?;
+            }
+            writer.write_digital_sample_stream(&[
+                vec![-32768i16; 64],
+                vec![1000i16; 64],
+                vec![-5i16; 8],
+            ])?;
+            writer.write_annotation(0, None, "Recording starts")?;
+            writer.write_annotation(250_001, Some(1_500_000), "Eyes\tclosed")?;
+            writer.write_annotation(
+                700_000,
+                Some(12),
+                "Description longer than forty bytes is cut",
+            )?;
+            writer.write_annotation(900_000, None, "Dropped")
+        };
+        for suffix in [".edf", ".bdf"] {
+            for position in [
+                AnnotationPosition::Start,
+                AnnotationPosition::Middle,
+                AnnotationPosition::End,
+            ] {
+                header.annotation_position = Some(position);
+                assert_backends_match(suffix, &header, &write);
+            }
+        }
+
+        // 没有数据记录时，edflib 为每个注释写入一个只含注释信号的数据记录
+        header.datarecord_duration = Some(std::time::Duration::from_micros(2500));
+        header.channels.truncate(1);
+        header.channels[0].sample_frequency = 400.0;
+        assert_backends_match(".edf", &header, &|writer| {
+            writer.write_annotation(1, Some(0), "Only annotations")?;
+            writer.write_annotation(2, Some(0), "Second")
+        });
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_writer_with_sink() {
+        let mut header = test_header(256.0);
+        header.annotation_channels = Some(2);
+        let write = |writer: &mut OpenEDFWriter| -> Result<()> {
+            writer.write_annotation(0, None, "Recording starts")?;
+            writer.write_annotation(250_001, Some(1_500_000), "Eyes closed")?;
+            for second in 0..3 {
+                let ramp: Vec<f64> = (0..256).map(|i| (second * 256 + i) as f64).collect();
+                writer.write_sample_stream(&[ramp.clone(), ramp])?;
+            }
+            writer.write_annotation(2_000_000, Some(0), "Recording ends")
+        };
+
+        let expected = write_with_backend(".edf", &header, Backend::Edflib, &write);
+        let mut writer = EDFWriterBuilder::from_header(std::path::PathBuf::new(), header.clone())
+            .overlong_text(OverlongText::Truncate)
+            .open_sink(std::io::Cursor::new(Vec::new()))
+            .unwrap();
+        write(&mut writer).unwrap();
+        writer.finish().unwrap();
+        let actual = writer
+            .into_sink::<std::io::Cursor<Vec<u8>>>()
+            .unwrap()
+            .into_inner();
+        assert_eq!(actual, expected);
+
+        // 流式写入时注释随下一个数据记录写入，须在数据记录之前写入注释
+        let mut writer = EDFWriterBuilder::from_header(std::path::PathBuf::new(), header.clone())
+            .open_stream(Vec::new(), 3)
+            .unwrap();
+        writer
+            .write_annotation(0, None, "Recording starts")
+            .unwrap();
+        writer
+            .write_annotation(250_001, Some(1_500_000), "Eyes closed")
+            .unwrap();
+        writer
+            .write_annotation(2_000_000, Some(0), "Recording ends")
+            .unwrap();
+        for second in 0..3 {
+            let ramp: Vec<f64> = (0..256).map(|i| (second * 256 + i) as f64).collect();
+            writer.write_sample_stream(&[ramp.clone(), ramp]).unwrap();
+        }
+        writer.finish().unwrap();
+        assert!(writer.file_path.as_os_str().is_empty());
+        let streamed = writer.into_sink::<Vec<u8>>().unwrap();
+        assert_eq!(streamed.len(), expected.len());
+        let mut expected = EDFReader::from_bytes(&expected).unwrap();
+        let mut streamed = EDFReader::from_bytes(&streamed).unwrap();
+        assert_same_header(&streamed.header, &expected.header);
+        assert_eq!(streamed.datarecords_in_file(), 3);
+        let annotations = |reader: &EDFReader| -> Vec<EDFAnnotation> {
+            reader.annotations().map(|a| a.unwrap()).collect()
+        };
+        assert_eq!(annotations(&streamed), annotations(&expected));
+        let read_all = |reader: &mut EDFReader| -> Vec<f64> {
+            let mut buf = vec![0.0; 3 * 256];
+            assert_eq!(
+                reader.read_physical_samples(1, &mut buf).unwrap(),
+                buf.len()
+            );
+            buf
+        };
+        assert_eq!(read_all(&mut streamed), read_all(&mut expected));
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_writer_with_stream_checks_datarecords() {
+        let header = test_header(256.0);
+        let second = || vec![vec![1.0; 256], vec![-1.0; 256]];
+
+        assert!(matches!(
+            EDFWriterBuilder::from_header(std::path::PathBuf::new(), header.clone())
+                .open_stream(Vec::new(), 0),
+            Err(EdfError::InvalidArgument { .. })
+        ));
+
+        // 写入多于声明的数据记录
+        let mut writer = EDFWriterBuilder::from_header(std::path::PathBuf::new(), header.clone())
+            .open_stream(Vec::new(), 1)
+            .unwrap();
+        writer.write_sample_stream(&second()).unwrap();
+        let err = writer.write_sample_stream(&second()).unwrap_err();
+        assert!(matches!(err, EdfError::InvalidArgument { .. }));
+
+        // 写入少于声明的数据记录
+        let mut writer = EDFWriterBuilder::from_header(std::path::PathBuf::new(), header.clone())
+            .open_stream(Vec::new(), 2)
+            .unwrap();
+        writer.write_sample_stream(&second()).unwrap();
+        let err = writer.finish().unwrap_err();
+        assert_eq!(err.operation(), "finish");
+        assert!(writer.into_sink::<Vec<u8>>().is_some());
+
+        // 每个数据记录只能容纳一个注释
+        let mut header = header;
+        header.annotation_channels = Some(1);
+        let mut writer = EDFWriterBuilder::from_header(std::path::PathBuf::new(), header)
+            .open_stream(Vec::new(), 1)
+            .unwrap();
+        writer.write_annotation(0, None, "First").unwrap();
+        writer.write_annotation(1, None, "Second").unwrap();
+        writer.write_sample_stream(&second()).unwrap();
+        let err = writer.finish().unwrap_err();
+        assert!(matches!(
+            err,
+            EdfError::AnnotationsDropped { dropped: 1, .. }
+        ));
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_native_writer_plain_formats() {
+        let mut header = test_header(256.0);
+        header.start_datetime = header.start_datetime.map(|start| start.with_nanosecond(0));
+        for (suffix, format) in [(".edf", FileFormat::Edf), (".bdf", FileFormat::Bdf)] {
+            header.file_format = Some(format);
+            let temp_file = NamedTempFile::with_suffix(suffix).unwrap();
+            let path = temp_file.path().to_path_buf();
+            // 默认的 edflib 后端也会改用 pure-Rust 后端
+            let mut writer = EDFWriterBuilder::from_header(path.clone(), header.clone())
+                .open()
+                .unwrap();
+            let err = writer.write_annotation(0, None, "Start").unwrap_err();
+            assert!(matches!(err, EdfError::InvalidArgument { .. }));
+            for second in 0..3 {
+                let ramp: Vec<f64> = (0..256).map(|i| (second * 256 + i) as f64).collect();
+                let negated = ramp.iter().map(|v| -v).collect();
+                writer.write_sample_stream(&[ramp, negated]).unwrap();
+            }
+            writer.finish().unwrap();
+
+            // 没有注释信号，保留字段为空
+            let bytes = std::fs::read(&path).unwrap();
+            let sample_size = if format.is_bdf() { 3 } else { 2 };
+            assert_eq!(bytes.len(), 3 * 256 + 3 * 2 * 256 * sample_size);
+            let reserved = if format.is_bdf() { "24BIT" } else { "" };
+            assert_eq!(
+                String::from_utf8_lossy(&bytes[192..236]).trim_end(),
+                reserved
+            );
+
+            let mut reader = EDFReader::open(path.clone()).unwrap();
+            assert_eq!(reader.header.file_format, Some(format));
+            assert_eq!(reader.header.channels.len(), 2);
+            assert_eq!(reader.datarecords_in_file(), 3);
+            assert_eq!(reader.annotations_in_file(), 0);
+            assert_eq!(reader.header.start_datetime, header.start_datetime);
+            // 标准 EDF/BDF 的患者和记录字段只保存姓名和设备
+            let patient_info = &reader.header.patient_info;
+            assert_eq!(patient_info.patient_name, header.patient_info.patient_name);
+            assert_eq!(patient_info.equipment, header.patient_info.equipment);
+            assert_eq!(patient_info.patient_code, "");
+            assert_eq!(patient_info.technician, "");
+            let parsed = EDFParsedHeader::open(path.clone()).unwrap();
+            assert_eq!(parsed.header.patient_info.patient_name, "Demo");
+            assert_eq!(parsed.header.patient_info.equipment, "Device");
+            let mut buf = vec![0.0; 3 * 256];
+            reader.read_physical_samples(1, &mut buf).unwrap();
+            assert!((buf[700] + 700.0).abs() < 0.1);
+            drop(reader);
+            assert_readers_match(&path, ReadAnnotations::All);
+        }
+
+        // 标准 EDF/BDF 既没有注释信号也没有亚秒级的开始时间
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        header.file_format = Some(FileFormat::Edf);
+        for change in [
+            |header: &mut EDFHeader| header.annotation_channels = Some(1),
+            |header: &mut EDFHeader| {
+                header.start_datetime = header.start_datetime.map(|s| s.with_nanosecond(1000))
+            },
+        ] {
+            let mut header = header.clone();
+            change(&mut header);
+            assert!(matches!(
+                EDFWriterBuilder::from_header(temp_file.path().to_path_buf(), header)
+                    .backend(Backend::PureRust)
+                    .open()
+                    .err()
+                    .unwrap(),
+                EdfError::InvalidArgument { .. }
+            ));
+        }
+    }
+
+    /// Asserts that edflib and the pure-Rust reader read the same from `path`.
+    #[cfg(feature = "pure-rust")]
+    fn assert_readers_match(path: &std::path::Path, read_annotations: ReadAnnotations) {
+        let mut expected =
+            EDFReader::open_with_annotations(path.to_path_buf(), read_annotations).unwrap();
+        let file = std::fs::File::open(path).unwrap();
+        let mut actual = EDFReader::from_reader_with_annotations(file, read_annotations).unwrap();
+
+        assert_same_header(&actual.header, &expected.header);
+        assert_eq!(actual.file_duration(), expected.file_duration());
+        assert_eq!(actual.datarecord_duration(), expected.datarecord_duration());
+        assert_eq!(actual.datarecords_in_file(), expected.datarecords_in_file());
+        assert_eq!(actual.annotations_in_file(), expected.annotations_in_file());
+        let annotations = |reader: &EDFReader| -> Vec<EDFAnnotation> {
+            reader.annotations().map(|a| a.unwrap()).collect()
+        };
+        assert_eq!(annotations(&actual), annotations(&expected));
+
+        for channel in 0..expected.header.channels.len() {
+            assert_eq!(
+                actual.samples_in_file(channel),
+                expected.samples_in_file(channel)
+            );
+            assert_eq!(
+                actual.samples_in_datarecord(channel),
+                expected.samples_in_datarecord(channel)
+            );
+            // 每次读 7 个样本，跨越数据记录的边界
+            let read_all = |reader: &mut EDFReader| -> Vec<f64> {
+                let mut samples = Vec::new();
+                let mut buf = [0.0; 7];
+                loop {
+                    let n = reader.read_physical_samples(channel, &mut buf).unwrap();
+                    samples.extend_from_slice(&buf[..n]);
+                    if n < buf.len() {
+                        return samples;
+                    }
+                }
+            };
+            assert_eq!(read_all(&mut actual), read_all(&mut expected));
+
+            let read_end = |reader: &EDFReader| -> (u64, Vec<i32>) {
+                use std::io::Seek;
+                let mut cursor = reader.cursor(channel).unwrap();
+                cursor.seek(std::io::SeekFrom::End(-10)).unwrap();
+                let mut buf = [0; 16];
+                let n = cursor.read_digital(&mut buf).unwrap();
+                let clamped = cursor.seek(std::io::SeekFrom::Current(100)).unwrap();
+                (clamped, buf[..n].to_vec())
+            };
+            assert_eq!(read_end(&actual), read_end(&expected));
+        }
+        assert!(actual.cursor(99).is_err());
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_native_reader_matches_edflib() {
+        let mut header = test_header(256.0);
+        header.patient_info.patient_name = "Zoë Ünal-Smith".to_string();
+        header.channels[1].physical_min = -0.123456789;
+        header.channels[1].physical_max = 1234.5;
+        header.channels[1].digital_min = -1000;
+        header.channels[1].digital_max = 1000;
+        header.channels.push(EDFChannel {
+            label: "Resp".to_string(),
+            sample_frequency: 32.0,
+            ..header.channels[1].clone()
+        });
+        header.datarecord_duration = Some(std::time::Duration::from_millis(250));
+        if let Some(start) = header.start_datetime.as_mut() {
+            *start = start.with_nanosecond(123_400_000);
+        }
+
+        for suffix in [".edf", ".bdf"] {
+            for annotation_channels in [1, 2] {
+                header.annotation_channels = Some(annotation_channels);
+                let temp_file = NamedTempFile::with_suffix(suffix).unwrap();
+                let path = temp_file.path().to_path_buf();
+                let mut writer = EDFWriterBuilder::from_header(path.clone(), header.clone())
+                    .open()
+                    .unwrap();
+                for record in 0..12 {
+                    let ramp = |len: usize, scale: f64| -> Vec<f64> {
+                        (0..len).map(|i| (i as f64 - 20.0) * scale).collect()
+                    };
+                    let scale = 37.3 * (record + 1) as f64;
+                    writer
+                        .write_sample_stream(&[ramp(64, scale), ramp(64, -scale), ramp(8, 0.1)])
+                        .unwrap();
+                }
+                writer
+                    .write_annotation(0, None, "Recording starts")
+                    .unwrap();
+                writer
+                    .write_annotation(250_001, Some(1_500_000), "Eyes\tclosed")
+                    .unwrap();
+                writer
+                    .write_annotation(500_000, None, "Recording ends")
+                    .unwrap();
+                writer
+                    .write_annotation(500_000, Some(0), "After the end")
+                    .unwrap();
+                writer.finish().unwrap();
+
+                for read_annotations in [
+                    ReadAnnotations::None,
+                    ReadAnnotations::UntilRecordingEnds,
+                    ReadAnnotations::All,
+                ] {
+                    assert_readers_match(&path, read_annotations);
+                }
+
+                let bytes = std::fs::read(&path).unwrap();
+                let reader = EDFReader::from_bytes(&bytes).unwrap();
+                assert_eq!(reader.file_path, std::path::PathBuf::new());
+                assert!(reader.annotations_in_file() >= 3);
+            }
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_reader_matches_edflib() {
+        let mut header = test_header(256.0);
+        header.channels[1].physical_min = -0.123456789;
+        header.channels[1].physical_max = 1234.5;
+        header.channels[1].digital_min = -1000;
+        header.channels[1].digital_max = 1000;
+        header.channels.push(EDFChannel {
+            label: "Resp".to_string(),
+            sample_frequency: 32.0,
+            ..header.channels[1].clone()
+        });
+        header.datarecord_duration = Some(std::time::Duration::from_millis(250));
+        header.annotation_position = Some(AnnotationPosition::Middle);
+
+        for suffix in [".edf", ".bdf"] {
+            let temp_file = NamedTempFile::with_suffix(suffix).unwrap();
+            let path = temp_file.path().to_path_buf();
+            let mut writer = EDFWriterBuilder::from_header(path.clone(), header.clone())
+                .open()
+                .unwrap();
+            writer
+                .write_annotation(0, None, "Recording starts")
+                .unwrap();
+            for record in 0..12 {
+                let ramp = |len: usize, scale: f64| -> Vec<f64> {
+                    (0..len).map(|i| (i as f64 - 20.0) * scale).collect()
+                };
+                let scale = 37.3 * (record + 1) as f64;
+                writer
+                    .write_sample_stream(&[ramp(64, scale), ramp(64, -scale), ramp(8, 0.1)])
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut expected = EDFReader::open(path.clone()).unwrap();
+            let reader = EDFMmapReader::open(path.clone()).unwrap();
+            assert_same_header(&reader.header, &expected.header);
+            assert_eq!(reader.datarecords_in_file(), 12);
+            assert_eq!(reader.datarecord_duration(), expected.datarecord_duration());
+
+            for channel in 0..header.channels.len() {
+                let samples = expected.samples_in_file(channel) as usize;
+                let mut digital = vec![0; samples];
+                expected
+                    .read_digital_samples(channel, &mut digital)
+                    .unwrap();
+                expected.cursor(channel).unwrap().rewind().unwrap();
+                let mut physical = vec![0.0; samples];
+                expected
+                    .read_physical_samples(channel, &mut physical)
+                    .unwrap();
+
+                let mut buf = vec![f64::NAN; samples + 1];
+                assert_eq!(
+                    reader.read_physical(channel, 0..12, &mut buf).unwrap(),
+                    samples
+                );
+                assert_eq!(buf[..samples], physical);
+                // 任意范围的数据记录
+                let per_record = reader.samples_in_datarecord(channel);
+                let n = reader.read_physical(channel, 5..7, &mut buf).unwrap();
+                assert_eq!(buf[..n], physical[5 * per_record..7 * per_record]);
+
+                let raw: Vec<i32> = (0..12)
+                    .flat_map(|record| reader.raw_samples(channel, record).unwrap().iter())
+                    .collect();
+                assert_eq!(raw, digital);
+                let raw = reader.raw_samples(channel, 11).unwrap();
+                assert_eq!(raw.len(), per_record);
+                assert_eq!(raw.get(per_record - 1), digital.last().copied());
+                assert_eq!(
+                    raw.as_bytes().len(),
+                    per_record * reader.layout().sample_size()
+                );
+            }
+
+            assert!(reader.datarecord(11).is_some());
+            assert!(reader.datarecord(12).is_none());
+            let mut buf = vec![0.0; 64 * 12];
+            for err in [
+                reader.raw_samples(3, 0).unwrap_err(),
+                reader.raw_samples(0, 12).unwrap_err(),
+                reader.raw_samples(0, usize::MAX).unwrap_err(),
+                reader.read_physical(0, 11..13, &mut buf).unwrap_err(),
+                reader
+                    .read_physical(0, 1..usize::MAX, &mut buf)
+                    .unwrap_err(),
+                reader.read_physical(0, 0..12, &mut buf[1..]).unwrap_err(),
+            ] {
+                assert!(matches!(err, EdfError::InvalidArgument { .. }));
+            }
+        }
+
+        let err = EDFMmapReader::open("does-not-exist.edf".into())
+            .err()
+            .unwrap();
+        assert!(matches!(err, EdfError::NoSuchFileOrDirectory { .. }));
+    }
+
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_native_reader_rejects_malformed_files() {
+        let temp_file = NamedTempFile::with_suffix(".edf").unwrap();
+        let path = temp_file.path().to_path_buf();
+        write_test_file(&path, 2);
+        let bytes = std::fs::read(&path).unwrap();
+        let parsed = EDFParsedHeader::from_bytes(&bytes).unwrap();
+        let tal = parsed.header_len + parsed.signals[2].offset;
+
+        let open = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            change(&mut bytes);
+            // edflib 也必须拒绝同样的文件
+            std::fs::write(&path, &bytes).unwrap();
+            assert!(EDFReader::open(path.clone()).is_err());
+            EDFReader::from_bytes(&bytes).err().unwrap()
+        };
+
+        let err = open(&|bytes| bytes[192..197].copy_from_slice(b"EDF+D"));
+        assert!(matches!(err, EdfError::FileIsDiscontinuous { .. }));
+        assert_eq!(err.operation(), "from_bytes");
+
+        // 第二个数据记录的开始时间与数据记录的时长不符
+        let err = open(&|bytes| bytes[tal + parsed.datarecord_len + 1] = b'7');
+        assert!(matches!(err, EdfError::FileContainsFormatErrors { .. }));
+
+        // 数据记录中 TAL 之后的填充必须全为 0
+        let err = open(&|bytes| bytes[tal + parsed.datarecord_len + 10] = b'x');
+        assert!(matches!(err, EdfError::FileContainsFormatErrors { .. }));
+
+        let err = open(&|bytes| bytes.truncate(bytes.len() - 1));
+        assert!(matches!(err, EdfError::InvalidHeader { .. }));
+    }
 }
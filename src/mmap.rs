@@ -0,0 +1,329 @@
+use crate::error::*;
+use crate::parser::*;
+use crate::writer::*;
+use memmap2::Mmap;
+use std::{fs::File, io::Cursor, ops::Range, path::PathBuf, time::Duration};
+
+/// The samples of one channel in one datarecord, borrowed from the memory map.
+///
+/// Samples are little-endian two's complement, 16 bits in EDF and 24 bits in BDF files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawSamples<'a> {
+    Edf(&'a [[u8; 2]]),
+    Bdf(&'a [[u8; 3]]),
+}
+
+impl<'a> RawSamples<'a> {
+    fn new(bytes: &'a [u8], bdf: bool) -> Self {
+        if bdf {
+            RawSamples::Bdf(bytes.as_chunks().0)
+        } else {
+            RawSamples::Edf(bytes.as_chunks().0)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RawSamples::Edf(samples) => samples.len(),
+            RawSamples::Bdf(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the samples as they are stored in the file.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match self {
+            RawSamples::Edf(samples) => samples.as_flattened(),
+            RawSamples::Bdf(samples) => samples.as_flattened(),
+        }
+    }
+
+    /// Returns the digital value of sample `index`, not clamped to the digital range.
+    pub fn get(&self, index: usize) -> Option<i32> {
+        match self {
+            RawSamples::Edf(samples) => samples.get(index).map(|&s| edf_sample(s)),
+            RawSamples::Bdf(samples) => samples.get(index).map(|&s| bdf_sample(s)),
+        }
+    }
+
+    /// Iterates over the digital values, not clamped to the digital range.
+    pub fn iter(&self) -> impl Iterator<Item = i32> + 'a {
+        let (edf, bdf) = match *self {
+            RawSamples::Edf(samples) => (samples, &[][..]),
+            RawSamples::Bdf(samples) => (&[][..], samples),
+        };
+        edf.iter()
+            .map(|&s| edf_sample(s))
+            .chain(bdf.iter().map(|&s| bdf_sample(s)))
+    }
+}
+
+fn edf_sample(sample: [u8; 2]) -> i32 {
+    i16::from_le_bytes(sample) as i32
+}
+
+fn bdf_sample([low, mid, high]: [u8; 3]) -> i32 {
+    // 24 位补码，最高字节做符号扩展
+    i32::from_le_bytes([low, mid, high, 0]) << 8 >> 8
+}
+
+/// Converts the digital values of a channel to physical values, like edflib
+#[derive(Debug, Clone, Copy)]
+struct Scaling {
+    digital_min: i32,
+    digital_max: i32,
+    bitvalue: f64,
+    /// Added to the digital value before multiplying with `bitvalue`
+    offset: f64,
+}
+
+impl Scaling {
+    /// Clamps and converts all of `samples` into `buf`, which has the same length.
+    ///
+    /// A plain loop over fixed-size samples, which the compiler vectorises.
+    fn convert<const N: usize>(
+        &self,
+        samples: &[[u8; N]],
+        buf: &mut [f64],
+        digital: impl Fn([u8; N]) -> i32,
+    ) {
+        for (value, &sample) in buf.iter_mut().zip(samples) {
+            let digital = digital(sample).clamp(self.digital_min, self.digital_max);
+            *value = self.bitvalue * (self.offset + digital as f64);
+        }
+    }
+}
+
+/// Reads EDF/EDF+/BDF/BDF+ files through a memory map, without copying the samples.
+///
+/// Meant for random access into large recordings: [`EDFMmapReader::raw_samples`] borrows the
+/// samples of a datarecord straight from the file, and [`EDFMmapReader::read_physical`] converts
+/// whole ranges of datarecords at once. Physical values are the same as those of
+/// [`EDFReader`](crate::EDFReader). All methods take `&self`, so a reader can be shared between
+/// threads. The header is read by [`EDFParsedHeader`]; unlike `EDFReader`, discontinuous EDF+D
+/// and BDF+D files are accepted, where the time of a datarecord is only known from its
+/// annotations. Annotations are not read. Only available with the `mmap` feature.
+///
+/// The file must not be modified or truncated while it is mapped, see [`Mmap`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use edflib::EDFMmapReader;
+/// # fn main() -> edflib::Result<()> {
+/// let reader = EDFMmapReader::open("recording.edf".into())?;
+/// // 第 2 小时开始的 10 个数据记录
+/// let first = (7200.0 / reader.datarecord_duration().as_secs_f64()) as usize;
+/// let records = first..first + 10;
+/// let mut window = vec![0.0; records.len() * reader.samples_in_datarecord(0)];
+/// reader.read_physical(0, records, &mut window)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct EDFMmapReader {
+    pub file_path: PathBuf,
+    /// Header of the data channels, as read by `EDFReader`
+    pub header: EDFHeader,
+    layout: EDFParsedHeader,
+    /// Index of every data channel in `layout.signals`
+    signals: Vec<usize>,
+    scaling: Vec<Scaling>,
+    mmap: Mmap,
+}
+
+impl EDFMmapReader {
+    /// Maps the file at `file_path` into memory and reads its header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::NoSuchFileOrDirectory`] if the file cannot be opened,
+    /// [`EdfError::FileReadError`] if it cannot be mapped and [`EdfError::InvalidHeader`] if the
+    /// header is malformed or the file size does not match it.
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        let context = || ErrorContext::new("open", None, file_path.clone());
+        let file = File::open(&file_path)
+            .map_err(|_| EdfError::NoSuchFileOrDirectory { context: context() })?;
+        // SAFETY: 文件在映射期间不得被修改，见类型文档
+        let mmap = unsafe { Mmap::map(&file) }
+            .map_err(|_| EdfError::FileReadError { context: context() })?;
+        let layout = EDFParsedHeader::parse("open", file_path.clone(), &mut Cursor::new(&mmap))?;
+
+        let signals: Vec<usize> = layout
+            .signals
+            .iter()
+            .enumerate()
+            .filter(|(_, signal)| !signal.annotation)
+            .map(|(index, _)| index)
+            .collect();
+        let scaling = layout
+            .header
+            .channels
+            .iter()
+            .map(|channel| {
+                // 与 edflib 相同的换算方式，保证物理值逐位相同
+                let bitvalue = (channel.physical_max - channel.physical_min)
+                    / (channel.digital_max - channel.digital_min) as f64;
+                Scaling {
+                    digital_min: channel.digital_min,
+                    digital_max: channel.digital_max,
+                    bitvalue,
+                    offset: channel.physical_max / bitvalue - channel.digital_max as f64,
+                }
+            })
+            .collect();
+        Ok(Self {
+            file_path,
+            header: layout.header.clone(),
+            layout,
+            signals,
+            scaling,
+            mmap,
+        })
+    }
+
+    /// Returns the parsed header with the layout of the datarecords.
+    pub fn layout(&self) -> &EDFParsedHeader {
+        &self.layout
+    }
+
+    pub fn datarecords_in_file(&self) -> usize {
+        self.layout.datarecords_in_file as usize
+    }
+
+    pub fn datarecord_duration(&self) -> Duration {
+        self.layout.datarecord_duration
+    }
+
+    /// Returns the number of samples of a data channel in every datarecord.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn samples_in_datarecord(&self, channel: usize) -> usize {
+        self.layout.signals[self.signals[channel]].samples_in_datarecord
+    }
+
+    /// Returns the bytes of datarecord `datarecord`, including the annotation signals, or `None`
+    /// if it is out of range.
+    pub fn datarecord(&self, datarecord: usize) -> Option<&[u8]> {
+        if datarecord >= self.datarecords_in_file() {
+            return None;
+        }
+        // 解析头信息时已检查文件大小
+        let len = self.layout.datarecord_len;
+        let start = self.layout.header_len + datarecord * len;
+        Some(&self.mmap[start..start + len])
+    }
+
+    /// Returns the samples of a data channel in datarecord `datarecord` without copying them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidArgument`] if `channel` or `datarecord` is out of range.
+    pub fn raw_samples(&self, channel: usize, datarecord: usize) -> Result<RawSamples<'_>> {
+        self.check_channel(channel, "raw_samples")?;
+        // 先检查，datarecord + 1 可能溢出
+        if datarecord >= self.datarecords_in_file() {
+            return Err(self.datarecords_error(channel, "raw_samples"));
+        }
+        let mut records = self.channel_bytes(channel, datarecord..datarecord + 1, "raw_samples")?;
+        let bytes = records.next().unwrap_or_default();
+        Ok(RawSamples::new(bytes, self.layout.format.is_bdf()))
+    }
+
+    /// Converts the samples of a data channel in `datarecords` to physical values.
+    ///
+    /// `buf` receives `datarecords.len() * samples_in_datarecord(channel)` values, which are the
+    /// same as those [`EDFReader::read_physical_samples`](crate::EDFReader::read_physical_samples)
+    /// reads. Returns the number of values written.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidArgument`] if `channel` or `datarecords` is out of range, or if
+    /// `buf` is too short.
+    pub fn read_physical(
+        &self,
+        channel: usize,
+        datarecords: Range<usize>,
+        buf: &mut [f64],
+    ) -> Result<usize> {
+        let operation = "read_physical";
+        self.check_channel(channel, operation)?;
+        self.check_datarecords(channel, datarecords.clone(), operation)?;
+        let samples_in_datarecord = self.samples_in_datarecord(channel);
+        let len = datarecords.len() * samples_in_datarecord;
+        if buf.len() < len {
+            return Err(EdfError::invalid_argument(
+                self.context(operation, Some(channel)),
+                format!("buf must hold {} samples", len),
+            ));
+        }
+
+        let scaling = self.scaling[channel];
+        let records = self.channel_bytes(channel, datarecords, operation)?;
+        for (bytes, buf) in records.zip(buf.chunks_exact_mut(samples_in_datarecord)) {
+            match RawSamples::new(bytes, self.layout.format.is_bdf()) {
+                RawSamples::Edf(samples) => scaling.convert(samples, buf, edf_sample),
+                RawSamples::Bdf(samples) => scaling.convert(samples, buf, bdf_sample),
+            }
+        }
+        Ok(len)
+    }
+
+    fn context(&self, operation: &'static str, channel: Option<usize>) -> ErrorContext {
+        ErrorContext::new(operation, channel, self.file_path.clone())
+    }
+
+    fn check_channel(&self, channel: usize, operation: &'static str) -> Result<()> {
+        if channel >= self.signals.len() {
+            return Err(EdfError::invalid_argument(
+                self.context(operation, Some(channel)),
+                format!(
+                    "channel out of range, the file has {} channels",
+                    self.signals.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_datarecords(
+        &self,
+        channel: usize,
+        datarecords: Range<usize>,
+        operation: &'static str,
+    ) -> Result<()> {
+        if datarecords.start > datarecords.end || datarecords.end > self.datarecords_in_file() {
+            return Err(self.datarecords_error(channel, operation));
+        }
+        Ok(())
+    }
+
+    fn datarecords_error(&self, channel: usize, operation: &'static str) -> EdfError {
+        EdfError::invalid_argument(
+            self.context(operation, Some(channel)),
+            format!(
+                "datarecords out of range, the file has {} datarecords",
+                self.datarecords_in_file()
+            ),
+        )
+    }
+
+    /// Returns the bytes of a data channel in each of `datarecords`.
+    fn channel_bytes(
+        &self,
+        channel: usize,
+        datarecords: Range<usize>,
+        operation: &'static str,
+    ) -> Result<impl Iterator<Item = &[u8]> + '_> {
+        self.check_datarecords(channel, datarecords.clone(), operation)?;
+        let signal = self.layout.signals[self.signals[channel]];
+        let len = signal.samples_in_datarecord * self.layout.sample_size();
+        Ok(datarecords
+            .filter_map(|datarecord| self.datarecord(datarecord))
+            .map(move |record| &record[signal.offset..signal.offset + len]))
+    }
+}
@@ -0,0 +1,955 @@
+use crate::backend::*;
+use crate::base::*;
+use crate::datetime::*;
+use crate::error::*;
+use crate::parser::{MONTHS, TIME_DIMENSION};
+use crate::utils::*;
+use std::{
+    any::Any,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Bytes of an annotation signal in every datarecord (`EDFLIB_ANNOTATION_BYTES`)
+const ANNOTATION_BYTES: usize = 120;
+
+/// Most signals edflib writes to a file, exclusive (`EDFLIB_MAXSIGNALS`)
+const MAX_SIGNALS: usize = 4096;
+
+/// Offset of the number of datarecords in the header
+const DATARECORDS_OFFSET: u64 = 236;
+
+/// edflib leaves the number of datarecords at -1 from this many datarecords on
+pub(crate) const MAX_COUNTED_DATARECORDS: i64 = 100_000_000;
+
+/// ASCII replacements of the characters 127 - 255, from edflib's `edflib_latin1_to_ascii`.
+/// 0x80 - 0x9f are taken from CP-1252.
+const LATIN1_TO_ASCII: &[u8; 129] = b".E.,F\".++^.S<E.Z..`'\"\".--~.s>e.zY.i....|....<...-....'u.....>...?AAAAAAECEEEEIIIIDNOOOOOxOUUUUYtsaaaaaaeceeeeiiiidnooooo:0uuuuyty";
+
+/// TAL separators of EDF+ annotations
+const TAL_DURATION: u8 = 21;
+const TAL_END: u8 = 20;
+
+/// A signal as edflib stores it after the `edf_set_*` calls
+struct Signal {
+    label: Vec<u8>,
+    transducer: Vec<u8>,
+    physical_dimension: Vec<u8>,
+    prefilter: Vec<u8>,
+    physical_max: f64,
+    physical_min: f64,
+    digital_max: i32,
+    digital_min: i32,
+    samples_in_datarecord: usize,
+}
+
+/// A field of the signal headers: its width, the value of the annotation signals and the value of a signal
+type SignalField<'a> = (usize, &'a [u8], fn(&Signal) -> Vec<u8>);
+
+/// An annotation kept until the file is closed
+struct Annotation {
+    /// Microseconds
+    onset: i64,
+    /// Microseconds, negative if unknown
+    duration: i64,
+    description: Vec<u8>,
+}
+
+/// A sink that can only be written to, for [`NativeWriter::stream`].
+///
+/// Streaming writers never seek, so seeking always fails.
+pub(crate) struct Unseekable<W>(pub W);
+
+impl<W: Write> Write for Unseekable<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W> Seek for Unseekable<W> {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Writes EDF+/BDF+ files in Rust, byte for byte like edflib, and plain EDF/BDF files.
+///
+/// Plain files have no annotation signal and the reserved field of the header is left blank,
+/// "24BIT" for BDF like the files of BioSemi devices.
+///
+/// Like edflib the header is written with the first datarecord, the annotations are kept in
+/// memory and written into the annotation signals on close, and the file is closed when the
+/// writer is dropped. A streaming writer instead writes the number of datarecords into the header
+/// up front and the annotations with the next datarecord, so it never seeks.
+pub(crate) struct NativeWriter<W: Write + Seek> {
+    /// Only taken by [`WriterBackend::into_sink`] after closing
+    sink: Option<W>,
+    path: PathBuf,
+    format: FileFormat,
+    overlong_text: OverlongText,
+    patient_code: Vec<u8>,
+    patient_name: Vec<u8>,
+    patient_additional: Vec<u8>,
+    /// b'M' or b'F'
+    sex: Option<u8>,
+    birthdate: Option<EDFDate>,
+    admin_code: Vec<u8>,
+    technician: Vec<u8>,
+    equipment: Vec<u8>,
+    recording_additional: Vec<u8>,
+    /// Whole seconds only, the current time is used if `None`
+    start_datetime: Option<EDFDateTime>,
+    /// Fraction of the second of the start time, in units of 100 ns
+    starttime_offset: i64,
+    signals: Vec<Signal>,
+    /// Physical value of one digital step and the digital offset of every signal
+    scaling: Vec<(f64, f64)>,
+    /// Duration of a datarecord in units of 100 ns
+    datarecord_duration: i64,
+    annotation_channels: usize,
+    annotation_position: AnnotationPosition,
+    datarecords: i64,
+    /// Number of datarecords of a streaming writer, written into the header up front
+    datarecords_in_header: Option<i64>,
+    annotations: Vec<Annotation>,
+    /// Number of annotations a streaming writer has written into datarecords
+    annotations_stored: usize,
+    closed: bool,
+}
+
+impl NativeWriter<BufWriter<File>> {
+    /// Creates the file at `settings.file_path`.
+    pub(crate) fn create(settings: &WriterSettings) -> Result<Self> {
+        let path = settings.file_path.to_path_buf();
+        let file = File::create(&path).map_err(|_| EdfError::NoSuchFileOrDirectory {
+            context: ErrorContext::new("open_file_writeonly_with_format", None, path),
+        })?;
+        Self::new(BufWriter::new(file), settings)
+    }
+}
+
+impl<W: Write> NativeWriter<Unseekable<W>> {
+    /// Creates a streaming writer for exactly `datarecords` datarecords.
+    pub(crate) fn stream(sink: W, settings: &WriterSettings, datarecords: usize) -> Result<Self> {
+        let mut writer = Self::new(Unseekable(sink), settings)?;
+        if datarecords == 0 || datarecords as i64 >= MAX_COUNTED_DATARECORDS {
+            // 出错时不写入任何内容
+            writer.closed = true;
+            return Err(EdfError::invalid_argument(
+                writer.context("open", None),
+                format!(
+                    "the number of datarecords must be from 1 to {}",
+                    MAX_COUNTED_DATARECORDS - 1
+                ),
+            ));
+        }
+        writer.datarecords_in_header = Some(datarecords as i64);
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> NativeWriter<W> {
+    /// Takes the header settings, checking them like the `edf_set_*` functions of edflib.
+    pub(crate) fn new(sink: W, settings: &WriterSettings) -> Result<Self> {
+        let header = settings.header;
+        let patient = &header.patient_info;
+        let mut writer = Self {
+            sink: Some(sink),
+            path: settings.file_path.to_path_buf(),
+            format: settings.format,
+            overlong_text: settings.overlong_text,
+            patient_code: Vec::new(),
+            patient_name: Vec::new(),
+            patient_additional: Vec::new(),
+            sex: None,
+            birthdate: None,
+            admin_code: Vec::new(),
+            technician: Vec::new(),
+            equipment: Vec::new(),
+            recording_additional: Vec::new(),
+            start_datetime: None,
+            starttime_offset: 0,
+            signals: Vec::with_capacity(header.channels.len()),
+            scaling: Vec::new(),
+            // 与 edflib 相同，以 100 ns 为单位
+            datarecord_duration: (settings.datarecord_duration.as_nanos() / 100) as i64,
+            // 标准 EDF/BDF 没有注释信号
+            annotation_channels: usize::from(settings.format.is_plus()),
+            annotation_position: header
+                .annotation_position
+                .unwrap_or(AnnotationPosition::End),
+            datarecords: 0,
+            datarecords_in_header: None,
+            annotations: Vec::new(),
+            annotations_stored: 0,
+            // 设置完成之前出错时不写入文件
+            closed: true,
+        };
+
+        if header.channels.len() >= MAX_SIGNALS {
+            return Err(EdfError::NumberOfSignalsInvalid {
+                context: writer.context("open_file_writeonly_with_format", None),
+            });
+        }
+
+        writer.equipment = writer.text(&patient.equipment, MAX_FIELD_LEN, "set_equipment", None)?;
+        writer.patient_name = writer.text(
+            &patient.patient_name,
+            MAX_FIELD_LEN,
+            "set_patientname",
+            None,
+        )?;
+        writer.patient_code = writer.text(
+            &patient.patient_code,
+            MAX_FIELD_LEN,
+            "set_patientcode",
+            None,
+        )?;
+        writer.patient_additional = writer.text(
+            &patient.patient_additional,
+            MAX_FIELD_LEN,
+            "set_patient_additional",
+            None,
+        )?;
+        if patient.sex >= 0 {
+            writer.sex = match patient.sex {
+                0 => Some(b'F'),
+                1 => Some(b'M'),
+                _ => return Err(writer.failed("set_sex", None)),
+            };
+        }
+        if let Some(birthdate) = patient.birthdate {
+            if !(1800..=3000).contains(&birthdate.year)
+                || !(1..=12).contains(&birthdate.month)
+                || !(1..=31).contains(&birthdate.day)
+            {
+                return Err(writer.failed("set_birthdate", None));
+            }
+            writer.birthdate = Some(birthdate);
+        }
+        writer.admin_code =
+            writer.text(&patient.admin_code, MAX_FIELD_LEN, "set_admincode", None)?;
+        writer.technician =
+            writer.text(&patient.technician, MAX_FIELD_LEN, "set_technician", None)?;
+        writer.recording_additional = writer.text(
+            &patient.recording_additional,
+            MAX_FIELD_LEN,
+            "set_recording_additional",
+            None,
+        )?;
+        if let Some(start) = header.start_datetime {
+            if !(1985..=2084).contains(&start.date.year)
+                || !(1..=12).contains(&start.date.month)
+                || !(1..=31).contains(&start.date.day)
+                || start.hour > 23
+                || start.minute > 59
+                || start.second > 59
+            {
+                return Err(writer.failed("set_startdatetime", None));
+            }
+            if !(0..TIME_DIMENSION as i32).contains(&start.subsecond())
+                || (start.subsecond() != 0 && !writer.format.is_plus())
+            {
+                return Err(writer.failed("set_subsecond_starttime", None));
+            }
+            writer.start_datetime = Some(start.with_nanosecond(0));
+            writer.starttime_offset = start.subsecond() as i64;
+        }
+
+        let max_digital = if writer.format.is_bdf() {
+            0x7fffff
+        } else {
+            0x7fff
+        };
+        for (i, ch) in header.channels.iter().enumerate() {
+            if ch.label.starts_with("EDF Annotations") || ch.label.starts_with("BDF Annotations") {
+                return Err(writer.failed("set_label", Some(i)));
+            }
+            if ch.digital_max > max_digital {
+                return Err(writer.failed("set_digital_maximum", Some(i)));
+            }
+            if ch.digital_min < -max_digital - 1 {
+                return Err(writer.failed("set_digital_minimum", Some(i)));
+            }
+            let samples_in_datarecord = settings.samples_in_datarecord[i];
+            if samples_in_datarecord < 1 || samples_in_datarecord > i32::MAX as usize {
+                return Err(writer.failed("set_samplefrequency", Some(i)));
+            }
+            let signal = Signal {
+                label: writer.text(&ch.label, MAX_LABEL_LEN, "set_label", Some(i))?,
+                transducer: writer.text(
+                    &ch.transducer,
+                    MAX_FIELD_LEN,
+                    "set_transducer",
+                    Some(i),
+                )?,
+                physical_dimension: writer.text(
+                    &ch.physical_dimension,
+                    MAX_PHYSICAL_DIMENSION_LEN,
+                    "set_physical_dimension",
+                    Some(i),
+                )?,
+                prefilter: writer.text(&ch.prefilter, MAX_FIELD_LEN, "set_prefilter", Some(i))?,
+                physical_max: ch.physical_max,
+                physical_min: ch.physical_min,
+                digital_max: ch.digital_max,
+                digital_min: ch.digital_min,
+                samples_in_datarecord,
+            };
+            writer.signals.push(signal);
+        }
+        writer.closed = false;
+        Ok(writer)
+    }
+
+    fn context(&self, operation: &'static str, signal: Option<usize>) -> ErrorContext {
+        ErrorContext::new(operation, signal, self.path.clone())
+    }
+
+    /// The error of an edflib function that returned -1
+    fn failed(&self, operation: &'static str, signal: Option<usize>) -> EdfError {
+        EdfError::Failed {
+            context: self.context(operation, signal),
+        }
+    }
+
+    fn sink(&mut self) -> &mut W {
+        self.sink
+            .as_mut()
+            .expect("the sink is only taken after closing")
+    }
+
+    fn write_error(&self, operation: &'static str) -> impl FnOnce(io::Error) -> EdfError {
+        let context = self.context(operation, None);
+        move |_| EdfError::FileWriteError { context }
+    }
+
+    /// Converts a header field to Latin-1 and removes the leading and trailing spaces, like edflib.
+    fn text(
+        &self,
+        text: &str,
+        max_len: usize,
+        operation: &'static str,
+        signal: Option<usize>,
+    ) -> Result<Vec<u8>> {
+        let truncate = self.overlong_text == OverlongText::Truncate;
+        let text =
+            to_cstring(text, TextEncoding::Latin1, max_len, truncate).map_err(|message| {
+                EdfError::invalid_argument(self.context(operation, signal), message)
+            })?;
+        let bytes = text.as_bytes();
+        let start = bytes.iter().position(|&c| c != b' ').unwrap_or(bytes.len());
+        let end = bytes
+            .iter()
+            .rposition(|&c| c != b' ')
+            .map_or(start, |i| i + 1);
+        Ok(bytes[start..end].to_vec())
+    }
+
+    fn sample_size(&self) -> usize {
+        if self.format.is_bdf() {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Index of the signal the annotation signals are stored in front of, the number of signals for the end
+    fn annotation_index(&self) -> usize {
+        match self.annotation_position {
+            AnnotationPosition::Start => 0,
+            AnnotationPosition::Middle => self.signals.len() / 2,
+            AnnotationPosition::End => self.signals.len(),
+        }
+    }
+
+    fn header_len(&self) -> usize {
+        (self.signals.len() + self.annotation_channels + 1) * 256
+    }
+
+    /// Checks the signals, computes their scaling and writes the header.
+    fn write_header(&mut self, operation: &'static str) -> Result<()> {
+        let context = self.context(operation, None);
+        let mut record_size = 0;
+        for signal in &self.signals {
+            if signal.digital_max == signal.digital_min {
+                return Err(EdfError::DigminIsDigmax { context });
+            }
+            if signal.digital_max < signal.digital_min {
+                return Err(EdfError::DigmaxLowerThanDigmin { context });
+            }
+            if signal.physical_max == signal.physical_min {
+                return Err(EdfError::PhysminIsPhysmax { context });
+            }
+            record_size += signal.samples_in_datarecord;
+        }
+        // EDF 的数据记录最大 10 MB，BDF 最大 15 MB
+        record_size =
+            record_size * self.sample_size() + ANNOTATION_BYTES * self.annotation_channels;
+        if record_size > 5 * 1024 * 1024 * self.sample_size() {
+            return Err(EdfError::DatarecordSizeTooBig { context });
+        }
+
+        self.scaling = self
+            .signals
+            .iter()
+            .map(|signal| {
+                let bitvalue = (signal.physical_max - signal.physical_min)
+                    / (signal.digital_max - signal.digital_min) as f64;
+                (
+                    bitvalue,
+                    signal.physical_max / bitvalue - signal.digital_max as f64,
+                )
+            })
+            .collect();
+        let start = *self.start_datetime.get_or_insert_with(now);
+
+        let mut header = Vec::with_capacity(self.header_len());
+        header.extend_from_slice(if self.format.is_bdf() {
+            b"\xffBIOSEMI"
+        } else {
+            b"0       "
+        });
+        header.extend(self.patient_field());
+        header.extend(self.recording_field(start));
+        header.extend_from_slice(
+            format!(
+                "{:02}.{:02}.{:02}{:02}.{:02}.{:02}",
+                start.date.day,
+                start.date.month,
+                start.date.year % 100,
+                start.hour,
+                start.minute,
+                start.second
+            )
+            .as_bytes(),
+        );
+        push_field(&mut header, self.header_len().to_string().as_bytes(), 8);
+        let reserved: &[u8] = match self.format {
+            FileFormat::Edf => b"",
+            FileFormat::EdfPlus => b"EDF+C",
+            FileFormat::Bdf => b"24BIT",
+            FileFormat::BdfPlus => b"BDF+C",
+        };
+        push_field(&mut header, reserved, 44);
+        let datarecords = match self.datarecords_in_header {
+            Some(datarecords) => datarecords.to_string(),
+            None => "-1".to_string(),
+        };
+        push_field(&mut header, datarecords.as_bytes(), 8);
+        if self.datarecord_duration == TIME_DIMENSION {
+            push_field(&mut header, b"1", 8);
+        } else {
+            let duration = self.datarecord_duration as f64 / TIME_DIMENSION as f64;
+            push_field(&mut header, format_number(duration).as_bytes(), 8);
+        }
+        push_field(
+            &mut header,
+            (self.signals.len() + self.annotation_channels)
+                .to_string()
+                .as_bytes(),
+            4,
+        );
+
+        let (label, digital_min, digital_max, samples): (&[u8], &[u8], &[u8], &[u8]) =
+            if self.format.is_bdf() {
+                (b"BDF Annotations", b"-8388608", b"8388607", b"40")
+            } else {
+                (b"EDF Annotations", b"-32768", b"32767", b"60")
+            };
+        let fields: [SignalField; 10] = [
+            (16, label, |s| ascii(&s.label)),
+            (80, b"", |s| ascii(&s.transducer)),
+            (8, b"", |s| ascii(&s.physical_dimension)),
+            (8, b"-1", |s| format_number(s.physical_min).into()),
+            (8, b"1", |s| format_number(s.physical_max).into()),
+            (8, digital_min, |s| s.digital_min.to_string().into()),
+            (8, digital_max, |s| s.digital_max.to_string().into()),
+            (80, b"", |s| ascii(&s.prefilter)),
+            (8, samples, |s| s.samples_in_datarecord.to_string().into()),
+            (32, b"", |_| Vec::new()),
+        ];
+        let annotation_index = self.annotation_index();
+        for (width, annotation, value) in &fields {
+            for (i, signal) in self.signals.iter().enumerate() {
+                if i == annotation_index {
+                    for _ in 0..self.annotation_channels {
+                        push_field(&mut header, annotation, *width);
+                    }
+                }
+                push_field(&mut header, &value(signal), *width);
+            }
+            if annotation_index == self.signals.len() {
+                for _ in 0..self.annotation_channels {
+                    push_field(&mut header, annotation, *width);
+                }
+            }
+        }
+
+        self.sink()
+            .write_all(&header)
+            .map_err(self.write_error(operation))
+    }
+
+    /// The EDF+ patient field: code, sex, birthdate, name and additional information.
+    ///
+    /// Plain EDF and BDF have no subfields, the field only holds the patient name.
+    fn patient_field(&self) -> Vec<u8> {
+        if !self.format.is_plus() {
+            return plain_field(&self.patient_name);
+        }
+        let mut field = Vec::with_capacity(80);
+        let mut rest = if self.birthdate.is_some() { 62 } else { 72 };
+        push_subfield(&mut field, &self.patient_code, &mut rest);
+        field.push(self.sex.unwrap_or(b'X'));
+        field.push(b' ');
+        match self.birthdate {
+            Some(date) => field.extend_from_slice(
+                format!(
+                    "{:02}-{}-{:02}{:02} ",
+                    date.day,
+                    String::from_utf8_lossy(MONTHS[date.month as usize - 1]),
+                    date.year / 100,
+                    date.year % 100
+                )
+                .as_bytes(),
+            ),
+            None => field.extend_from_slice(b"X "),
+        }
+        push_subfield(&mut field, &self.patient_name, &mut rest);
+        // 姓名后面的空格只在还有剩余空间时写入
+        field.pop();
+        if rest > 0 {
+            field.push(b' ');
+            rest -= 1;
+        }
+        push_additional(&mut field, &self.patient_additional, rest);
+        pad(&mut field, 80);
+        field
+    }
+
+    /// The EDF+ recording field: start date, admin code, technician, equipment and additional information
+    ///
+    /// Plain EDF and BDF have no subfields, the field only holds the equipment.
+    fn recording_field(&self, start: EDFDateTime) -> Vec<u8> {
+        if !self.format.is_plus() {
+            return plain_field(&self.equipment);
+        }
+        let mut field = format!(
+            "Startdate {:02}-{}-{:04} ",
+            start.date.day,
+            String::from_utf8_lossy(MONTHS[start.date.month as usize - 1]),
+            start.date.year
+        )
+        .into_bytes();
+        let mut rest = 52;
+        push_subfield(&mut field, &self.admin_code, &mut rest);
+        push_subfield(&mut field, &self.technician, &mut rest);
+        push_subfield(&mut field, &self.equipment, &mut rest);
+        push_additional(&mut field, &self.recording_additional, rest);
+        pad(&mut field, 80);
+        field
+    }
+
+    /// The time-keeping TAL of datarecord `datarecord`, e.g. "+12.5" followed by two separators
+    fn timekeeping(&self, datarecord: i64) -> Vec<u8> {
+        let time = datarecord * self.datarecord_duration + self.starttime_offset;
+        let mut tal = format!("+{}", time / TIME_DIMENSION);
+        if self.datarecord_duration % TIME_DIMENSION != 0 || self.starttime_offset != 0 {
+            tal.push_str(&format!(".{:07}", time % TIME_DIMENSION));
+        }
+        let mut tal = tal.into_bytes();
+        tal.extend_from_slice(&[TAL_END, TAL_END]);
+        tal
+    }
+
+    /// The TAL of an annotation, e.g. "+1.5" followed by the duration and the description
+    fn annotation_tal(&self, annotation: &Annotation) -> Vec<u8> {
+        let onset = annotation.onset + self.starttime_offset / 10;
+        let mut tal = format!("+{}", onset / 1_000_000).into_bytes();
+        if onset % 1_000_000 != 0 {
+            tal.extend_from_slice(format!(".{:06}", onset % 1_000_000).as_bytes());
+        }
+        if annotation.duration >= 0 {
+            tal.push(TAL_DURATION);
+            tal.extend_from_slice((annotation.duration / 1_000_000).to_string().as_bytes());
+            if annotation.duration % 1_000_000 != 0 {
+                tal.extend_from_slice(
+                    format!(".{:06}", annotation.duration % 1_000_000).as_bytes(),
+                );
+            }
+        }
+        tal.push(TAL_END);
+        tal.extend_from_slice(&annotation.description);
+        tal.push(TAL_END);
+        tal
+    }
+
+    /// The annotation signals of datarecord `datarecord`, holding one of `annotations` each.
+    fn annotation_signals(&self, datarecord: i64, annotations: &[Annotation]) -> Vec<u8> {
+        let mut signals = Vec::with_capacity(ANNOTATION_BYTES * self.annotation_channels);
+        for channel in 0..self.annotation_channels {
+            let mut tal = Vec::with_capacity(ANNOTATION_BYTES * 2);
+            if channel == 0 {
+                // 第一个注释信号以时间戳开头
+                tal.extend(self.timekeeping(datarecord));
+                if !annotations.is_empty() {
+                    tal.push(0);
+                }
+            }
+            if let Some(annotation) = annotations.get(channel) {
+                tal.extend(self.annotation_tal(annotation));
+            }
+            // 超长的部分被截断
+            tal.resize(ANNOTATION_BYTES, 0);
+            signals.extend(tal);
+        }
+        signals
+    }
+
+    /// Writes whole datarecords, converting every sample with `digital` and the scaling of its signal.
+    fn write_records<T: Copy>(
+        &mut self,
+        samples: &[T],
+        samples_per_record: usize,
+        operation: &'static str,
+        digital: impl Fn(T, (f64, f64)) -> i32,
+    ) -> Result<()> {
+        let layout_len: usize = self
+            .signals
+            .iter()
+            .map(|signal| signal.samples_in_datarecord)
+            .sum();
+        if samples.is_empty()
+            || samples_per_record != layout_len
+            || !samples.len().is_multiple_of(samples_per_record)
+        {
+            return Err(EdfError::invalid_argument(
+                self.context(operation, None),
+                "samples length must be a full sample of samplefrequency",
+            ));
+        }
+        let records = (samples.len() / samples_per_record) as i64;
+        if let Some(datarecords) = self.datarecords_in_header {
+            if self.datarecords + records > datarecords {
+                return Err(EdfError::invalid_argument(
+                    self.context(operation, None),
+                    format!("the header declares {} datarecords", datarecords),
+                ));
+            }
+        }
+        let sample_size = self.sample_size();
+        let annotation_index = self.annotation_index();
+        let mut record = Vec::new();
+        for chunk in samples.chunks(samples_per_record) {
+            if self.datarecords == 0 {
+                self.write_header(operation)?;
+            }
+            // 流式写入时注释随下一个数据记录写入，否则在关闭时写入
+            let mut annotations: &[Annotation] = &[];
+            if self.datarecords_in_header.is_some() {
+                let stored = self.annotations_stored;
+                self.annotations_stored =
+                    (stored + self.annotation_channels).min(self.annotations.len());
+                annotations = &self.annotations[stored..self.annotations_stored];
+            }
+            let annotation_signals = self.annotation_signals(self.datarecords, annotations);
+
+            record.clear();
+            let mut offset = 0;
+            for (i, signal) in self.signals.iter().enumerate() {
+                if i == annotation_index {
+                    record.extend_from_slice(&annotation_signals);
+                }
+                let end = offset + signal.samples_in_datarecord;
+                for &sample in &chunk[offset..end] {
+                    let value = digital(sample, self.scaling[i])
+                        .clamp(signal.digital_min, signal.digital_max);
+                    record.extend_from_slice(&value.to_le_bytes()[..sample_size]);
+                }
+                offset = end;
+            }
+            if annotation_index == self.signals.len() {
+                record.extend_from_slice(&annotation_signals);
+            }
+            self.sink()
+                .write_all(&record)
+                .map_err(self.write_error(operation))?;
+            self.datarecords += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes the annotations into the annotation signals and the number of datarecords into the header.
+    fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let operation = "finish";
+
+        if let Some(datarecords) = self.datarecords_in_header {
+            self.sink().flush().map_err(self.write_error(operation))?;
+            if self.datarecords != datarecords {
+                return Err(EdfError::invalid_argument(
+                    self.context(operation, None),
+                    format!(
+                        "{} of the {} datarecords declared in the header were written",
+                        self.datarecords, datarecords
+                    ),
+                ));
+            }
+            let dropped = self.annotations.len() - self.annotations_stored;
+            if dropped > 0 {
+                return Err(EdfError::AnnotationsDropped {
+                    context: self.context(operation, None),
+                    dropped,
+                });
+            }
+            return Ok(());
+        }
+
+        if self.datarecords == 0 {
+            self.write_header(operation)?;
+            // edflib 为每个注释写入只含注释信号的数据记录
+            for _ in 0..self.annotations.len() {
+                let record = self.annotation_signals(self.datarecords, &[]);
+                self.sink()
+                    .write_all(&record)
+                    .map_err(self.write_error(operation))?;
+                self.datarecords += 1;
+            }
+        }
+        if self.datarecords < MAX_COUNTED_DATARECORDS {
+            // 覆盖头信息中的 "-1"
+            let mut count = self.datarecords.to_string().into_bytes();
+            pad(&mut count, 2);
+            let sink = self.sink();
+            sink.seek(SeekFrom::Start(DATARECORDS_OFFSET))
+                .and_then(|_| sink.write_all(&count))
+                .map_err(self.write_error(operation))?;
+        }
+
+        let sample_size = self.sample_size();
+        let signal_bytes: Vec<usize> = self
+            .signals
+            .iter()
+            .map(|signal| signal.samples_in_datarecord * sample_size)
+            .collect();
+        let datarecord_len =
+            ANNOTATION_BYTES * self.annotation_channels + signal_bytes.iter().sum::<usize>();
+        let offset = self.header_len()
+            + signal_bytes[..self.annotation_index()]
+                .iter()
+                .sum::<usize>();
+        // 每个数据记录的注释信号依次保存注释，放不下的注释被丢弃
+        let patches: Vec<Vec<u8>> = self
+            .annotations
+            .chunks(self.annotation_channels.max(1))
+            .take(self.datarecords as usize)
+            .enumerate()
+            .map(|(datarecord, annotations)| {
+                self.annotation_signals(datarecord as i64, annotations)
+            })
+            .collect();
+        for (datarecord, signals) in patches.iter().enumerate() {
+            let sink = self.sink();
+            sink.seek(SeekFrom::Start(
+                (offset + datarecord * datarecord_len) as u64,
+            ))
+            .and_then(|_| sink.write_all(signals))
+            .map_err(self.write_error(operation))?;
+        }
+        self.sink().flush().map_err(self.write_error(operation))
+    }
+}
+
+impl<W: Write + Seek + Send + 'static> WriterBackend for NativeWriter<W> {
+    fn set_number_of_annotation_signals(&mut self, annot_signals: usize) -> Result<()> {
+        if self.datarecords > 0
+            || !self.format.is_plus()
+            || !(1..=MAX_ANNOTATION_CHANNELS).contains(&annot_signals)
+        {
+            return Err(self.failed("set_number_of_annotation_signals", None));
+        }
+        self.annotation_channels = annot_signals;
+        Ok(())
+    }
+
+    fn write_physical_records(&mut self, samples: &[f64], samples_per_record: usize) -> Result<()> {
+        // 与 edflib 相同，向零取整后再限制在数字范围内
+        self.write_records(
+            samples,
+            samples_per_record,
+            "blockwrite_physical_samples",
+            |value, (bitvalue, offset)| (value / bitvalue - offset) as i32,
+        )
+    }
+
+    fn write_digital_records(&mut self, samples: &[i32], samples_per_record: usize) -> Result<()> {
+        self.write_records(
+            samples,
+            samples_per_record,
+            "blockwrite_digital_samples",
+            |value, _| value,
+        )
+    }
+
+    fn write_annotation(&mut self, onset: i64, duration: i64, description: &str) -> Result<()> {
+        let truncate = self.overlong_text == OverlongText::Truncate;
+        let description = to_cstring(
+            description,
+            TextEncoding::Utf8,
+            MAX_WRITE_ANNOTATION_LEN,
+            truncate,
+        )
+        .map_err(|message| {
+            EdfError::invalid_argument(self.context("write_annotation", None), message)
+        })?;
+        if onset < 0 || !self.format.is_plus() {
+            return Err(self.failed("write_annotation", None));
+        }
+        // edflib 把控制字符替换为 '.'
+        let description = description
+            .as_bytes()
+            .iter()
+            .map(|&c| if c < 32 { b'.' } else { c })
+            .collect();
+        self.annotations.push(Annotation {
+            onset,
+            duration,
+            description,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.close()
+    }
+
+    fn into_sink(mut self: Box<Self>) -> Option<Box<dyn Any + Send>> {
+        if !self.closed {
+            return None;
+        }
+        let sink = self.sink.take()?;
+        Some(Box::new(sink))
+    }
+}
+
+impl<W: Write + Seek> Drop for NativeWriter<W> {
+    /// Closes the file like [`WriterBackend::finish`], ignoring errors.
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Appends `value` left-aligned in a field of `width` bytes, cut off if it is longer.
+fn push_field(header: &mut Vec<u8>, value: &[u8], width: usize) {
+    let len = value.len().min(width);
+    header.extend_from_slice(&value[..len]);
+    header.resize(header.len() + width - len, b' ');
+}
+
+/// Pads `field` with spaces to `len` bytes.
+fn pad(field: &mut Vec<u8>, len: usize) {
+    if field.len() < len {
+        field.resize(len, b' ');
+    }
+}
+
+/// A free text header field of plain EDF and BDF, padded to 80 bytes
+fn plain_field(value: &[u8]) -> Vec<u8> {
+    let mut field = ascii(value);
+    field.truncate(80);
+    pad(&mut field, 80);
+    field
+}
+
+/// Replaces the characters that are not printable ASCII, like `edflib_latin1_to_ascii`.
+fn ascii(text: &[u8]) -> Vec<u8> {
+    text.iter()
+        .map(|&c| match c {
+            0..=31 => b'.',
+            32..=126 => c,
+            _ => LATIN1_TO_ASCII[c as usize - 127],
+        })
+        .collect()
+}
+
+/// Appends a subfield of the patient or recording field followed by a space, or "X " if it is empty.
+///
+/// Like edflib, the subfield is cut to the `rest` of the field that is left and spaces become '_'.
+fn push_subfield(field: &mut Vec<u8>, value: &[u8], rest: &mut usize) {
+    if value.is_empty() || *rest == 0 {
+        field.extend_from_slice(b"X ");
+        return;
+    }
+    let len = value.len().min(*rest);
+    *rest -= len;
+    field.extend(
+        ascii(&value[..len])
+            .into_iter()
+            .map(|c| if c == b' ' { b'_' } else { c }),
+    );
+    field.push(b' ');
+}
+
+/// Appends the additional information of the patient or recording field, cut to `rest` bytes.
+fn push_additional(field: &mut Vec<u8>, value: &[u8], rest: usize) {
+    let len = value.len().min(rest);
+    field.extend(ascii(&value[..len]));
+}
+
+/// Formats a number like `edflib_snprint_number_nonlocalized`: the integer part and up to nine
+/// decimals without trailing zeros.
+fn format_number(value: f64) -> String {
+    let integer = value as i32;
+    let mut text = String::new();
+    if value < 0.0 {
+        text.push('-');
+    }
+    text.push_str(&integer.unsigned_abs().to_string());
+    let fraction = ((value - integer as f64) * 1e9) as i32;
+    if fraction != 0 {
+        let decimals = format!("{:09}", fraction.unsigned_abs());
+        text.push('.');
+        text.push_str(decimals.trim_end_matches('0'));
+    }
+    text
+}
+
+/// Returns the current time in UTC, the standard library has no time zones.
+fn now() -> EDFDateTime {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs()) as i64;
+    // 由 1970-01-01 起的天数换算公历日期
+    let days = seconds.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let time = seconds.rem_euclid(86_400);
+
+    EDFDateTime::new(
+        EDFDate::new(year as i32, month as u32, day as u32),
+        (time / 3600) as u32,
+        (time / 60 % 60) as u32,
+        (time % 60) as u32,
+    )
+}
@@ -0,0 +1,448 @@
+use crate::backend::*;
+use crate::base::*;
+use crate::error::*;
+use crate::parser::*;
+use crate::reader::*;
+use crate::writer::*;
+use std::{
+    cell::{Cell, RefCell},
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+/// Longest description edflib keeps of an annotation (`EDFLIB_MAX_ANNOTATION_LEN`)
+const MAX_ANNOTATION_LEN: usize = 512;
+
+/// Longest duration text edflib keeps of an annotation
+const MAX_DURATION_LEN: usize = 15;
+
+/// TAL separators of EDF+ annotations
+const TAL_DURATION: u8 = 21;
+const TAL_END: u8 = 20;
+
+/// A data signal and the values edflib derives from its header
+struct Signal {
+    /// Offset in bytes from the start of a datarecord
+    offset: usize,
+    samples_in_datarecord: i64,
+    samples_in_file: i64,
+    digital_min: i32,
+    digital_max: i32,
+    bitvalue: f64,
+    /// Added to the digital value before multiplying with `bitvalue`
+    physical_offset: f64,
+    /// Sample position, like edflib's `sample_pntr`
+    position: Cell<i64>,
+}
+
+/// Reads EDF/EDF+/BDF/BDF+ files in Rust from any `Read + Seek` source, like edflib.
+///
+/// The header is read by [`EDFParsedHeader`] and the annotations when the file is opened; samples
+/// are read on demand. Like edflib, discontinuous files are rejected.
+pub(crate) struct NativeReader<R: Read + Seek> {
+    source: RefCell<R>,
+    sample_size: usize,
+    header_len: u64,
+    datarecord_len: u64,
+    signals: Vec<Signal>,
+    annotations: Vec<EDFAnnotation>,
+}
+
+impl<R: Read + Seek> NativeReader<R> {
+    /// Reads the header and the selected annotations, errors are reported as `operation`.
+    pub(crate) fn open(
+        mut source: R,
+        operation: &'static str,
+        read_annotations: ReadAnnotations,
+    ) -> Result<(Self, EDFParsedHeader)> {
+        let parsed = EDFParsedHeader::parse(operation, PathBuf::new(), &mut source)?;
+        let context = ErrorContext::new(operation, None, PathBuf::new());
+        if parsed.discontinuous {
+            return Err(EdfError::FileIsDiscontinuous { context });
+        }
+
+        let signals = parsed
+            .signals
+            .iter()
+            .filter(|layout| !layout.annotation)
+            .zip(&parsed.header.channels)
+            .map(|(layout, channel)| {
+                // 与 edflib 相同的换算方式，保证物理值逐位相同
+                let bitvalue = (channel.physical_max - channel.physical_min)
+                    / (channel.digital_max - channel.digital_min) as f64;
+                Signal {
+                    offset: layout.offset,
+                    samples_in_datarecord: layout.samples_in_datarecord as i64,
+                    samples_in_file: layout.samples_in_datarecord as i64
+                        * parsed.datarecords_in_file,
+                    digital_min: channel.digital_min,
+                    digital_max: channel.digital_max,
+                    bitvalue,
+                    physical_offset: channel.physical_max / bitvalue - channel.digital_max as f64,
+                    position: Cell::new(0),
+                }
+            })
+            .collect();
+
+        let annotations = if parsed.format.is_plus() {
+            self::read_annotations(&mut source, &parsed, context, read_annotations)?
+        } else {
+            Vec::new()
+        };
+        let reader = Self {
+            source: RefCell::new(source),
+            sample_size: parsed.sample_size(),
+            header_len: parsed.header_len as u64,
+            datarecord_len: parsed.datarecord_len as u64,
+            signals,
+            annotations,
+        };
+        Ok((reader, parsed))
+    }
+
+    fn context(&self, operation: &'static str, edfsignal: Option<i32>) -> ErrorContext {
+        ErrorContext::new(
+            operation,
+            edfsignal.map(|edfsignal| edfsignal as usize),
+            PathBuf::new(),
+        )
+    }
+
+    /// Returns the number of annotations read when the file was opened.
+    pub(crate) fn annotations_in_file(&self) -> i64 {
+        self.annotations.len() as i64
+    }
+
+    fn signal(&self, edfsignal: i32, operation: &'static str) -> Result<&Signal> {
+        usize::try_from(edfsignal)
+            .ok()
+            .and_then(|index| self.signals.get(index))
+            .ok_or_else(|| EdfError::Failed {
+                context: self.context(operation, Some(edfsignal)),
+            })
+    }
+
+    /// Reads the samples from the sample position, clamping them to the digital range.
+    fn read_samples<T>(
+        &self,
+        edfsignal: i32,
+        buf: &mut [T],
+        operation: &'static str,
+        convert: impl Fn(&Signal, i32) -> T,
+    ) -> Result<usize> {
+        let signal = self.signal(edfsignal, operation)?;
+        let start = signal.position.get();
+        let n = (buf.len() as i64)
+            .min(signal.samples_in_file - start)
+            .max(0) as usize;
+
+        let mut source = self.source.borrow_mut();
+        let mut bytes = Vec::new();
+        let mut done = 0;
+        while done < n {
+            // 每次读取一个数据记录中连续的样本
+            let position = start + done as i64;
+            let record = (position / signal.samples_in_datarecord) as u64;
+            let sample = position % signal.samples_in_datarecord;
+            let count = ((signal.samples_in_datarecord - sample) as usize).min(n - done);
+            let offset = self.header_len
+                + record * self.datarecord_len
+                + (signal.offset + sample as usize * self.sample_size) as u64;
+            bytes.resize(count * self.sample_size, 0);
+            source
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| source.read_exact(&mut bytes))
+                .map_err(|_| EdfError::FileReadError {
+                    context: self.context(operation, Some(edfsignal)),
+                })?;
+
+            let values = bytes.chunks_exact(self.sample_size).map(|sample| {
+                let value = match *sample {
+                    [low, high] => i16::from_le_bytes([low, high]) as i32,
+                    // 24 位补码，最高字节做符号扩展
+                    [low, mid, high] => i32::from_le_bytes([low, mid, high, 0]) << 8 >> 8,
+                    _ => unreachable!(),
+                };
+                value.clamp(signal.digital_min, signal.digital_max)
+            });
+            for (value, digital) in buf[done..done + count].iter_mut().zip(values) {
+                *value = convert(signal, digital);
+            }
+            done += count;
+        }
+
+        signal.position.set(start + n as i64);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek + Send> ReaderBackend for NativeReader<R> {
+    fn context(&self, operation: &'static str, edfsignal: Option<i32>) -> ErrorContext {
+        NativeReader::context(self, operation, edfsignal)
+    }
+
+    fn read_physical_samples(&self, edfsignal: i32, buf: &mut [f64]) -> Result<usize> {
+        self.read_samples(
+            edfsignal,
+            buf,
+            "read_physical_samples",
+            |signal, digital| signal.bitvalue * (signal.physical_offset + digital as f64),
+        )
+    }
+
+    fn read_digital_samples(&self, edfsignal: i32, buf: &mut [i32]) -> Result<usize> {
+        self.read_samples(edfsignal, buf, "read_digital_samples", |_, digital| digital)
+    }
+
+    fn seek(&self, edfsignal: i32, pos: SeekFrom) -> Result<i64> {
+        let signal = self.signal(edfsignal, "seek")?;
+        let position = match pos {
+            SeekFrom::Start(offset) => offset.min(i64::MAX as u64) as i64,
+            SeekFrom::Current(offset) => signal.position.get().saturating_add(offset),
+            SeekFrom::End(offset) => signal.samples_in_file.saturating_add(offset),
+        };
+        signal
+            .position
+            .set(position.clamp(0, signal.samples_in_file));
+        Ok(signal.position.get())
+    }
+
+    fn tell(&self, edfsignal: i32) -> Result<i64> {
+        Ok(self.signal(edfsignal, "tell")?.position.get())
+    }
+
+    fn rewind(&self, edfsignal: i32) -> Result<()> {
+        self.signal(edfsignal, "rewind")?.position.set(0);
+        Ok(())
+    }
+
+    fn annotation(&self, n: i64) -> Result<EDFAnnotation> {
+        usize::try_from(n)
+            .ok()
+            .and_then(|n| self.annotations.get(n))
+            .cloned()
+            .ok_or_else(|| EdfError::Failed {
+                context: self.context("get_annotation", None),
+            })
+    }
+}
+
+/// Reads the annotations from the annotation signals of all datarecords, like edflib's
+/// `edflib_get_annotations`.
+fn read_annotations<R: Read + Seek>(
+    source: &mut R,
+    parsed: &EDFParsedHeader,
+    context: ErrorContext,
+    read_annotations: ReadAnnotations,
+) -> Result<Vec<EDFAnnotation>> {
+    let format_error = || EdfError::FileContainsFormatErrors {
+        context: context.clone(),
+    };
+    let read_error = || EdfError::FileReadError {
+        context: context.clone(),
+    };
+    let sample_size = parsed.sample_size();
+    let datarecord_duration = parsed.datarecord_duration.as_nanos() as i64 / 100;
+    let annotation_signals: Vec<_> = parsed
+        .signals
+        .iter()
+        .filter(|layout| layout.annotation)
+        .map(|layout| {
+            let start = layout.offset;
+            start..start + layout.samples_in_datarecord * sample_size
+        })
+        .collect();
+
+    let mut annotations = Vec::new();
+    let mut record = vec![0u8; parsed.datarecord_len];
+    let mut start_offset = 0;
+    let mut elapsed = 0;
+    source
+        .seek(SeekFrom::Start(parsed.header_len as u64))
+        .map_err(|_| read_error())?;
+    for i in 0..parsed.datarecords_in_file {
+        source.read_exact(&mut record).map_err(|_| read_error())?;
+
+        for (r, range) in annotation_signals.iter().enumerate() {
+            let tal = &record[range.clone()];
+            if tal.last() != Some(&0) {
+                return Err(format_error());
+            }
+
+            if r == 0 {
+                // 第一个注释信号以记录开始时间的 TAL 开头
+                let end = tal[..tal.len().saturating_sub(2)]
+                    .iter()
+                    .position(|&b| b == TAL_END)
+                    .ok_or_else(format_error)?;
+                let onset = until_nul(&tal[..end]);
+                if tal[end + 1] != TAL_END || !is_onset_number(onset) {
+                    return Err(format_error());
+                }
+                let time = long_time(onset);
+                if i > 0 {
+                    if time - elapsed != datarecord_duration {
+                        return Err(format_error());
+                    }
+                } else if !(0..TIME_DIMENSION).contains(&time) {
+                    return Err(format_error());
+                } else {
+                    start_offset = time;
+                    if read_annotations == ReadAnnotations::None {
+                        return Ok(annotations);
+                    }
+                }
+                elapsed = time;
+            }
+
+            let stop = read_annotations == ReadAnnotations::UntilRecordingEnds
+                && annotation_signals.len() == 1;
+            read_tals(tal, r > 0, start_offset, stop, &mut annotations).ok_or_else(format_error)?;
+        }
+    }
+
+    Ok(annotations)
+}
+
+/// Reads the TALs of one annotation signal of a datarecord into `annotations`.
+///
+/// The first annotation of the first signal of a datarecord is the time-keeping annotation and is
+/// skipped unless `not_first_signal` is set. With `stop` the rest of the signal is skipped after
+/// an annotation "Recording ends". Returns `None` if the TALs are malformed.
+fn read_tals(
+    tal: &[u8],
+    not_first_signal: bool,
+    start_offset: i64,
+    stop: bool,
+    annotations: &mut Vec<EDFAnnotation>,
+) -> Option<()> {
+    let mut text_start = 0;
+    let mut zero = 0;
+    let mut onset: Option<&[u8]> = None;
+    let mut duration: Option<&[u8]> = None;
+    let mut duration_start = false;
+    let mut annots_in_tal = 0;
+    let mut annots_in_record = 0;
+
+    for (k, &b) in tal.iter().enumerate() {
+        if b == 0 {
+            if zero == 0 {
+                // TAL 以 20 0 结尾
+                if k > 0 && tal[k - 1] != TAL_END {
+                    return None;
+                }
+                onset = None;
+                duration = None;
+                duration_start = false;
+                annots_in_tal = 0;
+            }
+            zero += 1;
+            text_start = k + 1;
+            continue;
+        }
+        if zero > 1 {
+            return None;
+        }
+        zero = 0;
+
+        if b != TAL_END && b != TAL_DURATION {
+            continue;
+        }
+        let field = &tal[text_start..k];
+        text_start = k + 1;
+
+        if b == TAL_DURATION {
+            // 一个 TAL 只能有一个紧跟在开始时间后面的持续时间
+            if duration.is_some() || duration_start || onset.is_some() || annots_in_tal > 0 {
+                return None;
+            }
+            duration_start = true;
+        }
+
+        match onset {
+            Some(onset) if b == TAL_END && !duration_start => {
+                if not_first_signal || annots_in_record > 0 {
+                    let description = &field[..field.len().min(MAX_ANNOTATION_LEN)];
+                    let printable =
+                        |&c: &u8| c >= 32 && c != 127 || matches!(c, b'\t' | b'\n' | b'\r');
+                    if !description.iter().all(printable) {
+                        return None;
+                    }
+                    annotations.push(EDFAnnotation {
+                        onset: from_edflib_time(long_time(onset) - start_offset),
+                        duration: duration.map(|duration| from_edflib_time(long_time(duration))),
+                        description: String::from_utf8_lossy(description).trim_end().to_string(),
+                    });
+                    if stop && description.starts_with(b"Recording ends") {
+                        return Some(());
+                    }
+                }
+                annots_in_tal += 1;
+                annots_in_record += 1;
+            }
+            None => {
+                if !is_onset_number(field) {
+                    return None;
+                }
+                onset = Some(field);
+            }
+            Some(_) => {
+                // 此时 duration_start 一定为真
+                if !is_duration_number(field) {
+                    return None;
+                }
+                duration = Some(&field[..field.len().min(MAX_DURATION_LEN)]);
+                duration_start = false;
+            }
+        }
+    }
+    Some(())
+}
+
+/// Returns `bytes` up to the first NUL, like C strings.
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..len]
+}
+
+/// Checks a TAL onset: a sign followed by a decimal number, like `edflib_is_onset_number`.
+fn is_onset_number(text: &[u8]) -> bool {
+    text.len() >= 2 && matches!(text[0], b'+' | b'-') && is_duration_number(&text[1..])
+}
+
+/// Checks a TAL duration: a decimal number, like `edflib_is_duration_number`.
+fn is_duration_number(text: &[u8]) -> bool {
+    !text.is_empty()
+        && text.first() != Some(&b'.')
+        && text.last() != Some(&b'.')
+        && text.iter().filter(|&&b| b == b'.').count() <= 1
+        && text.iter().all(|&b| b == b'.' || b.is_ascii_digit())
+}
+
+/// Converts a checked TAL time into units of 100 ns like `edflib_get_long_time`, ignoring digits
+/// beyond the resolution.
+fn long_time(text: &[u8]) -> i64 {
+    let (negative, text) = match text.first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (seconds, fraction) = match text.iter().position(|&b| b == b'.') {
+        Some(dot) => (&text[..dot], &text[dot + 1..]),
+        None => (text, &[][..]),
+    };
+    let seconds = seconds.iter().fold(0i64, |value, &b| {
+        value.wrapping_mul(10).wrapping_add((b - b'0') as i64)
+    });
+    let fraction = fraction
+        .iter()
+        .chain(std::iter::repeat(&b'0'))
+        .take(7)
+        .fold(0i64, |value, &b| value * 10 + (b - b'0') as i64);
+    let value = seconds.wrapping_mul(TIME_DIMENSION).wrapping_add(fraction);
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
@@ -0,0 +1,615 @@
+use crate::base::*;
+use crate::datetime::*;
+use crate::error::*;
+use crate::writer::*;
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Time values of the header in units of 100 ns per second, like `EDFLIB_TIME_DIMENSION`
+pub(crate) const TIME_DIMENSION: i64 = 10_000_000;
+
+/// Size of the main header, and of the header of every signal
+const HEADER_BLOCK_LEN: usize = 256;
+
+/// Most signals edflib reads from a file (`EDFLIB_MAXSIGNALS`)
+const MAX_SIGNALS: i64 = 4096;
+
+/// Offset of each field in the signal headers, in multiples of the number of signals
+const LABEL: (usize, usize) = (0, 16);
+const TRANSDUCER: (usize, usize) = (16, 80);
+const PHYSICAL_DIMENSION: (usize, usize) = (96, 8);
+const PHYSICAL_MIN: (usize, usize) = (104, 8);
+const PHYSICAL_MAX: (usize, usize) = (112, 8);
+const DIGITAL_MIN: (usize, usize) = (120, 8);
+const DIGITAL_MAX: (usize, usize) = (128, 8);
+const PREFILTER: (usize, usize) = (136, 80);
+const SAMPLES: (usize, usize) = (216, 8);
+const RESERVED: (usize, usize) = (224, 32);
+
+pub(crate) const MONTHS: [&[u8; 3]; 12] = [
+    b"JAN", b"FEB", b"MAR", b"APR", b"MAY", b"JUN", b"JUL", b"AUG", b"SEP", b"OCT", b"NOV", b"DEC",
+];
+
+/// Where a signal is stored in a datarecord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalLayout {
+    /// Number of samples of the signal in every datarecord
+    pub samples_in_datarecord: usize,
+    /// Offset in bytes of the first sample of the signal from the start of a datarecord
+    pub offset: usize,
+    /// `true` for the "EDF Annotations"/"BDF Annotations" signals of EDF+/BDF+
+    pub annotation: bool,
+}
+
+/// The header of an EDF/EDF+/BDF/BDF+ file, read by the pure-Rust parser.
+///
+/// `header` matches the header of an [`EDFReader`](crate::EDFReader) opened on the same file:
+/// annotation signals are left out of `channels`, and `annotation_channels` and
+/// `annotation_position` are `None`. Unlike edflib, the parser also accepts discontinuous
+/// EDF+D/BDF+D files. Only available with the `pure-rust` feature.
+#[derive(Debug, Clone)]
+pub struct EDFParsedHeader {
+    pub header: EDFHeader,
+    pub format: FileFormat,
+    /// `true` for EDF+D and BDF+D files, whose datarecords may have gaps between them
+    pub discontinuous: bool,
+    /// Size of the header in bytes, where the first datarecord starts
+    pub header_len: usize,
+    /// Size of a datarecord in bytes
+    pub datarecord_len: usize,
+    pub datarecords_in_file: i64,
+    pub datarecord_duration: Duration,
+    /// Layout of every signal of a datarecord in file order, including annotation signals
+    pub signals: Vec<SignalLayout>,
+}
+
+impl EDFParsedHeader {
+    /// Reads the header of an EDF/EDF+/BDF/BDF+ file without edflib.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::NoSuchFileOrDirectory`] if the file cannot be opened,
+    /// [`EdfError::FileReadError`] if it cannot be read and [`EdfError::InvalidHeader`] if the
+    /// header is malformed or the file size does not match it.
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        let parser = Parser::new("open", file_path.clone());
+        let mut file = File::open(&file_path).map_err(|_| EdfError::NoSuchFileOrDirectory {
+            context: parser.context(None),
+        })?;
+        parser.parse(&mut file)
+    }
+
+    /// Reads the header from `reader`, which holds a whole file starting at offset 0.
+    ///
+    /// # Errors
+    ///
+    /// See [`EDFParsedHeader::open`].
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Parser::new("read", PathBuf::new()).parse(reader)
+    }
+
+    /// Reads the header from the contents of a whole file.
+    ///
+    /// # Errors
+    ///
+    /// See [`EDFParsedHeader::open`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Parser::new("from_bytes", PathBuf::new()).parse(&mut Cursor::new(bytes))
+    }
+
+    /// Reads the header from `reader` for the public function `operation` of another type.
+    pub(crate) fn parse<R: Read + Seek>(
+        operation: &'static str,
+        path: PathBuf,
+        reader: &mut R,
+    ) -> Result<Self> {
+        Parser::new(operation, path).parse(reader)
+    }
+
+    /// Returns the size of a sample in bytes, 2 for EDF and 3 for BDF.
+    pub fn sample_size(&self) -> usize {
+        if self.format.is_bdf() {
+            3
+        } else {
+            2
+        }
+    }
+}
+
+/// Reports errors with the name of the public function and the path being parsed
+struct Parser {
+    operation: &'static str,
+    path: PathBuf,
+}
+
+impl Parser {
+    fn new(operation: &'static str, path: PathBuf) -> Self {
+        Self { operation, path }
+    }
+
+    fn context(&self, signal: Option<usize>) -> ErrorContext {
+        ErrorContext::new(self.operation, signal, self.path.clone())
+    }
+
+    fn invalid(&self, signal: Option<usize>, message: impl Into<String>) -> EdfError {
+        EdfError::InvalidHeader {
+            context: self.context(signal),
+            message: message.into(),
+        }
+    }
+
+    fn read_error(&self) -> EdfError {
+        EdfError::FileReadError {
+            context: self.context(None),
+        }
+    }
+
+    fn read_exact<R: Read + Seek>(&self, reader: &mut R, pos: u64, buf: &mut [u8]) -> Result<()> {
+        reader
+            .seek(SeekFrom::Start(pos))
+            .and_then(|_| reader.read_exact(buf))
+            .map_err(|_| self.read_error())
+    }
+
+    /// Checks that a field only contains printable ASCII, like edflib does.
+    fn text<'a>(&self, bytes: &'a [u8], name: &str, signal: Option<usize>) -> Result<&'a str> {
+        if bytes.iter().all(|b| (32..=126).contains(b)) {
+            // 可打印的 ASCII 一定是合法的 UTF-8
+            Ok(std::str::from_utf8(bytes).unwrap())
+        } else {
+            Err(self.invalid(
+                signal,
+                format!("{} contains non-printable characters", name),
+            ))
+        }
+    }
+
+    /// Parses an integer field, which may be padded with spaces on the right only.
+    fn integer(&self, bytes: &[u8], name: &str, signal: Option<usize>) -> Result<i64> {
+        let text = self.text(bytes, name, signal)?.trim_end_matches(' ');
+        let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(self.invalid(signal, format!("{} is not an integer", name)));
+        }
+        text.parse()
+            .map_err(|_| self.invalid(signal, format!("{} is out of range", name)))
+    }
+
+    /// Parses a decimal field, which may be padded with spaces on the right only.
+    fn number(&self, bytes: &[u8], name: &str, signal: Option<usize>) -> Result<f64> {
+        let text = self.text(bytes, name, signal)?.trim_end_matches(' ');
+        // 只接受 edflib 认可的字符，排除 "inf"、"NaN" 等
+        let allowed = |b: u8| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E');
+        match text.parse() {
+            Ok(value) if text.bytes().all(allowed) => Ok(value),
+            _ => Err(self.invalid(signal, format!("{} is not a number", name))),
+        }
+    }
+
+    fn parse<R: Read + Seek>(&self, reader: &mut R) -> Result<EDFParsedHeader> {
+        let mut main = [0u8; HEADER_BLOCK_LEN];
+        self.read_exact(reader, 0, &mut main)?;
+
+        // BDF 的版本号以 0xFF 开头
+        let bdf = main[0] == 0xff;
+        if bdf {
+            if &main[1..8] != b"BIOSEMI" {
+                return Err(self.invalid(None, "version is not \"\\xFFBIOSEMI\""));
+            }
+        } else if &main[..8] != b"0       " {
+            return Err(self.invalid(None, "version is not \"0\""));
+        }
+
+        let patient = self.text(&main[8..88], "patient", None)?;
+        let recording = self.text(&main[88..168], "recording", None)?;
+
+        let date = self.text(&main[168..176], "startdate", None)?.as_bytes();
+        let (day, month, short_year) = split_dotted(date)
+            .filter(|&(day, month, _)| (1..=31).contains(&day) && (1..=12).contains(&month))
+            .ok_or_else(|| self.invalid(None, "startdate is not a valid dd.mm.yy date"))?;
+        // 与 edflib 相同：85 - 99 表示 1985 - 1999，00 - 84 表示 2000 - 2084
+        let year = short_year as i32 + if short_year > 84 { 1900 } else { 2000 };
+
+        let time = self.text(&main[176..184], "starttime", None)?.as_bytes();
+        let (hour, minute, second) = split_dotted(time)
+            .filter(|&(hour, minute, second)| hour <= 23 && minute <= 59 && second <= 59)
+            .ok_or_else(|| self.invalid(None, "starttime is not a valid hh.mm.ss time"))?;
+
+        let signal_count = self.integer(&main[252..256], "number of signals", None)?;
+        if !(1..=MAX_SIGNALS).contains(&signal_count) {
+            return Err(self.invalid(None, "number of signals must be from 1 to 4096"));
+        }
+        let signal_count = signal_count as usize;
+        let header_len = (signal_count + 1) * HEADER_BLOCK_LEN;
+        if self.integer(&main[184..192], "number of bytes in header", None)? != header_len as i64 {
+            return Err(self.invalid(
+                None,
+                "number of bytes in header does not match the number of signals",
+            ));
+        }
+
+        let reserved = self.text(&main[192..236], "reserved field", None)?;
+        let reserved = reserved.trim_end_matches(' ');
+        let (format, discontinuous) = match (bdf, reserved) {
+            (false, "EDF+C") => (FileFormat::EdfPlus, false),
+            (false, "EDF+D") => (FileFormat::EdfPlus, true),
+            (false, "") => (FileFormat::Edf, false),
+            (false, _) => {
+                return Err(self.invalid(None, "reserved field of an EDF file is not empty"))
+            }
+            (true, "BDF+C") => (FileFormat::BdfPlus, false),
+            (true, "BDF+D") => (FileFormat::BdfPlus, true),
+            (true, _) => (FileFormat::Bdf, false),
+        };
+        let plus = format.is_plus();
+
+        let datarecords_in_file = self.integer(&main[236..244], "number of datarecords", None)?;
+        if datarecords_in_file < 1 {
+            return Err(self.invalid(None, "number of datarecords must be at least 1"));
+        }
+
+        let duration_field = &main[244..252];
+        let duration_seconds = self.number(duration_field, "datarecord duration", None)?;
+        if duration_seconds < -0.000001 {
+            return Err(self.invalid(None, "datarecord duration is negative"));
+        }
+        let duration_units = time_units(duration_field)
+            .unwrap_or_else(|| (duration_seconds.max(0.0) * TIME_DIMENSION as f64).round() as i64);
+
+        let mut signal_headers = vec![0u8; signal_count * HEADER_BLOCK_LEN];
+        self.read_exact(reader, HEADER_BLOCK_LEN as u64, &mut signal_headers)?;
+        let field = |(offset, width): (usize, usize), signal: usize| {
+            &signal_headers[offset * signal_count + signal * width..][..width]
+        };
+
+        let mut labels = Vec::with_capacity(signal_count);
+        let mut annotations = Vec::with_capacity(signal_count);
+        for signal in 0..signal_count {
+            let label = self.text(field(LABEL, signal), "label", Some(signal))?;
+            let (annotation, label) = match label {
+                "EDF Annotations " => (format == FileFormat::EdfPlus, "EDF_Annotations"),
+                "BDF Annotations " => (format == FileFormat::BdfPlus, "BDF_Annotations"),
+                label => (false, label),
+            };
+            // edflib 把其它格式中的注释信号当作普通信号，标签改为 "EDF_Annotations"
+            labels.push(label.trim_end());
+            annotations.push(annotation);
+        }
+        let annotation_count = annotations.iter().filter(|&&a| a).count();
+        if plus && annotation_count == 0 {
+            return Err(self.invalid(None, "an EDF+/BDF+ file has no annotation signal"));
+        }
+        if (annotation_count != signal_count || !plus) && duration_seconds < 0.0000001 {
+            return Err(self.invalid(None, "datarecord duration must be positive"));
+        }
+
+        let (sample_size, digital_limit) = if bdf { (3, 0x7f_ffff) } else { (2, 0x7fff) };
+        let mut channels = Vec::new();
+        let mut signals = Vec::with_capacity(signal_count);
+        let mut datarecord_len = 0;
+        for signal in 0..signal_count {
+            let annotation = annotations[signal];
+            let index = Some(signal);
+
+            let transducer = self.text(field(TRANSDUCER, signal), "transducer", index)?;
+            let physical_dimension = self.text(
+                field(PHYSICAL_DIMENSION, signal),
+                "physical dimension",
+                index,
+            )?;
+            let physical_min =
+                self.number(field(PHYSICAL_MIN, signal), "physical minimum", index)?;
+            let physical_max =
+                self.number(field(PHYSICAL_MAX, signal), "physical maximum", index)?;
+            if physical_min == physical_max {
+                return Err(self.invalid(index, "physical minimum equals physical maximum"));
+            }
+
+            let digital_min = self.integer(field(DIGITAL_MIN, signal), "digital minimum", index)?;
+            let digital_max = self.integer(field(DIGITAL_MAX, signal), "digital maximum", index)?;
+            let digital_range = -digital_limit - 1..=digital_limit;
+            if !digital_range.contains(&digital_min) || !digital_range.contains(&digital_max) {
+                return Err(self.invalid(index, "digital limits are out of range"));
+            }
+            if annotation && (digital_min != -digital_limit - 1 || digital_max != digital_limit) {
+                return Err(self.invalid(
+                    index,
+                    "digital limits of an annotation signal are not the full range",
+                ));
+            }
+            if digital_max <= digital_min {
+                return Err(self.invalid(index, "digital maximum is not above digital minimum"));
+            }
+
+            let prefilter = self.text(field(PREFILTER, signal), "prefilter", index)?;
+            if annotation && !(transducer.trim_end().is_empty() && prefilter.trim_end().is_empty())
+            {
+                return Err(self.invalid(
+                    index,
+                    "transducer and prefilter of an annotation signal are not empty",
+                ));
+            }
+
+            let samples = self.integer(field(SAMPLES, signal), "number of samples", index)?;
+            if samples < 1 {
+                return Err(self.invalid(index, "number of samples must be at least 1"));
+            }
+
+            let reserved = self.text(field(RESERVED, signal), "reserved field", index)?;
+            if format != FileFormat::Bdf && !reserved.trim_end().is_empty() {
+                return Err(self.invalid(index, "reserved field is not empty"));
+            }
+
+            let samples = samples as usize;
+            signals.push(SignalLayout {
+                samples_in_datarecord: samples,
+                offset: datarecord_len,
+                annotation,
+            });
+            datarecord_len += samples * sample_size;
+
+            if !annotation {
+                channels.push(EDFChannel {
+                    label: labels[signal].to_string(),
+                    transducer: transducer.trim_end().to_string(),
+                    digital_max: digital_max as i32,
+                    digital_min: digital_min as i32,
+                    physical_max,
+                    physical_min,
+                    physical_dimension: physical_dimension.trim_end().to_string(),
+                    prefilter: prefilter.trim_end().to_string(),
+                    sample_frequency: samples as f64 * TIME_DIMENSION as f64
+                        / duration_units as f64,
+                });
+            }
+        }
+        let max_datarecord_len = if bdf { 15 } else { 10 } * 1024 * 1024;
+        if datarecord_len > max_datarecord_len {
+            return Err(self.invalid(None, "datarecord size is too big"));
+        }
+
+        let patient_info = if plus {
+            let mut patient_info = self.plus_patient(patient.as_bytes())?;
+            self.plus_recording(recording.as_bytes(), (day, month, year), &mut patient_info)?;
+            patient_info
+        } else {
+            // 标准 EDF/BDF 没有结构化的患者信息，只有原始的 patient/recording 字段
+            EDFPatientInfo {
+                patient_name: patient.trim_end().to_string(),
+                equipment: recording.trim_end().to_string(),
+                ..EDFPatientInfo::default()
+            }
+        };
+
+        let file_len = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|_| self.read_error())?;
+        if file_len != (header_len + datarecord_len * datarecords_in_file as usize) as u64 {
+            return Err(self.invalid(None, "file size does not match the header"));
+        }
+
+        // EDF+/BDF+ 的开始时间的小数部分保存在第一个数据记录的第一个注释信号中
+        let subsecond = match signals.iter().find(|signal| signal.annotation) {
+            Some(signal) => {
+                let mut tal = vec![0u8; signal.samples_in_datarecord * sample_size];
+                self.read_exact(reader, (header_len + signal.offset) as u64, &mut tal)?;
+                self.start_offset(&tal)?
+            }
+            None => 0,
+        };
+
+        Ok(EDFParsedHeader {
+            header: EDFHeader {
+                patient_info,
+                channels,
+                start_datetime: Some(
+                    EDFDateTime::new(EDFDate::new(year, month, day), hour, minute, second)
+                        .with_nanosecond(subsecond as u32 * 100),
+                ),
+                datarecord_duration: Some(Duration::from_nanos(duration_units as u64 * 100)),
+                annotation_channels: None,
+                annotation_position: None,
+                file_format: Some(format),
+            },
+            format,
+            discontinuous,
+            header_len,
+            datarecord_len,
+            datarecords_in_file,
+            datarecord_duration: Duration::from_nanos(duration_units as u64 * 100),
+            signals,
+        })
+    }
+
+    /// Parses the EDF+ patient field: "code sex birthdate name additional".
+    fn plus_patient(&self, field: &[u8]) -> Result<EDFPatientInfo> {
+        let invalid = |message: &str| self.invalid(None, format!("patient field {}", message));
+
+        let mut pos = 0;
+        let code = word(field, &mut pos);
+        if code.is_empty() || code.len() > 72 {
+            return Err(invalid("has no valid patient code"));
+        }
+        let sex = match word(field, &mut pos) {
+            b"M" => 1,
+            b"F" => 0,
+            b"X" => -1,
+            _ => return Err(invalid("has no valid sex")),
+        };
+        let birthdate = match word(field, &mut pos) {
+            b"X" => None,
+            date => Some(
+                plus_date(date)
+                    .filter(|_| code.len() <= 64)
+                    .ok_or_else(|| invalid("has no valid birthdate"))?,
+            ),
+        };
+        let name = word(field, &mut pos);
+        if name.is_empty() {
+            return Err(invalid("has no patient name"));
+        }
+        let additional = &field[pos.min(field.len())..];
+
+        Ok(EDFPatientInfo {
+            patient_name: subfield(name),
+            patient_code: optional_subfield(code),
+            sex,
+            birthdate,
+            patient_additional: String::from_utf8_lossy(additional).trim_end().to_string(),
+            ..EDFPatientInfo::default()
+        })
+    }
+
+    /// Parses the EDF+ recording field: "Startdate dd-MMM-yyyy admincode technician equipment
+    /// additional". The start date must match the startdate field of the header.
+    fn plus_recording(
+        &self,
+        field: &[u8],
+        (day, month, year): (u32, u32, i32),
+        patient_info: &mut EDFPatientInfo,
+    ) -> Result<()> {
+        let invalid = |message: &str| self.invalid(None, format!("recording field {}", message));
+
+        if !field.starts_with(b"Startdate ") {
+            return Err(invalid("does not start with \"Startdate\""));
+        }
+        let mut pos = 10;
+        match word(field, &mut pos) {
+            b"X" => {}
+            date => {
+                let date = plus_date(date).ok_or_else(|| invalid("has no valid start date"))?;
+                if date != EDFDate::new(year, month, day) {
+                    return Err(invalid("does not match the startdate of the header"));
+                }
+            }
+        }
+        let admin_code = word(field, &mut pos);
+        let technician = word(field, &mut pos);
+        // edflib 要求设备字段在倒数第二个字符之前开始
+        let equipment_start = pos;
+        let equipment = word(field, &mut pos);
+        if admin_code.is_empty()
+            || technician.is_empty()
+            || equipment.is_empty()
+            || equipment_start > 79
+        {
+            return Err(invalid("has no valid admincode, technician and equipment"));
+        }
+        let additional = &field[pos.min(field.len())..];
+
+        patient_info.admin_code = optional_subfield(admin_code);
+        patient_info.technician = optional_subfield(technician);
+        patient_info.equipment = optional_subfield(equipment);
+        patient_info.recording_additional =
+            String::from_utf8_lossy(additional).trim_end().to_string();
+        Ok(())
+    }
+
+    /// Reads the fraction of the second of the start time from the time-keeping TAL at the start
+    /// of the first annotation signal, in units of 100 ns.
+    fn start_offset(&self, tal: &[u8]) -> Result<i64> {
+        let invalid = |message: &str| self.invalid(None, format!("first annotation {}", message));
+
+        if tal.last() != Some(&0) {
+            return Err(invalid("signal is not terminated"));
+        }
+        let end = tal[..tal.len().saturating_sub(2)]
+            .iter()
+            .position(|&b| b == 20)
+            .filter(|&end| tal[end + 1] == 20)
+            .ok_or_else(|| invalid("has no time-keeping onset"))?;
+        let onset = &tal[..end];
+        let value = match onset.first() {
+            Some(b'+') => time_units(&onset[1..]),
+            Some(b'-') => time_units(&onset[1..]).map(|value| -value),
+            _ => None,
+        }
+        .filter(|_| onset.len() >= 2 && onset[1] != b'.' && onset.last() != Some(&b'.'))
+        .ok_or_else(|| invalid("onset is not a number"))?;
+        if !(0..TIME_DIMENSION).contains(&value) {
+            return Err(invalid("onset is not within the first second"));
+        }
+        Ok(value)
+    }
+}
+
+/// Splits "dd.mm.yy" or "hh.mm.ss" into its three numbers.
+fn split_dotted(text: &[u8]) -> Option<(u32, u32, u32)> {
+    let number = |i: usize| {
+        let (high, low) = (text[i], text[i + 1]);
+        (high.is_ascii_digit() && low.is_ascii_digit())
+            .then(|| (high - b'0') as u32 * 10 + (low - b'0') as u32)
+    };
+    if text[2] != b'.' || text[5] != b'.' {
+        return None;
+    }
+    Some((number(0)?, number(3)?, number(6)?))
+}
+
+/// Parses an EDF+ date "dd-MMM-yyyy" with an upper case month.
+fn plus_date(text: &[u8]) -> Option<EDFDate> {
+    if text.len() != 11 || text[2] != b'-' || text[6] != b'-' {
+        return None;
+    }
+    let digits = |range: std::ops::Range<usize>| {
+        text[range].iter().try_fold(0u32, |value, &b| {
+            b.is_ascii_digit().then(|| value * 10 + (b - b'0') as u32)
+        })
+    };
+    let day = digits(0..2).filter(|day| (1..=31).contains(day))?;
+    let month = MONTHS.iter().position(|&month| month == &text[3..6])? as u32 + 1;
+    let year = digits(7..11)?;
+    Some(EDFDate::new(year as i32, month, day))
+}
+
+/// Converts a decimal number of seconds like "0.25" into units of 100 ns, ignoring padding and
+/// digits beyond the resolution. `None` if it is not a plain decimal number.
+fn time_units(text: &[u8]) -> Option<i64> {
+    let text = std::str::from_utf8(text).ok()?.trim_end_matches(' ');
+    let text = text.strip_prefix('+').unwrap_or(text);
+    let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if seconds.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(seconds) || !all_digits(fraction) {
+        return None;
+    }
+    let seconds: i64 = if seconds.is_empty() {
+        0
+    } else {
+        seconds.parse().ok()?
+    };
+    let fraction = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(7)
+        .fold(0, |value, b| value * 10 + (b - b'0') as i64);
+    Some(seconds * TIME_DIMENSION + fraction)
+}
+
+/// Returns the subfield starting at `pos` up to the next space and moves `pos` past the space.
+fn word<'a>(field: &'a [u8], pos: &mut usize) -> &'a [u8] {
+    let rest = &field[(*pos).min(field.len())..];
+    let len = rest.iter().position(|&b| b == b' ').unwrap_or(rest.len());
+    *pos += len + 1;
+    &rest[..len]
+}
+
+/// EDF+ subfields use '_' in place of spaces.
+fn subfield(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).replace('_', " ")
+}
+
+/// Like [`subfield`], with "X" meaning the subfield is not known.
+fn optional_subfield(bytes: &[u8]) -> String {
+    if bytes == b"X" {
+        String::new()
+    } else {
+        subfield(bytes)
+    }
+}
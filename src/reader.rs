@@ -0,0 +1,403 @@
+use crate::backend::*;
+use crate::base::*;
+use crate::cursor::*;
+use crate::datetime::*;
+use crate::error::*;
+#[cfg(feature = "pure-rust")]
+use crate::native_reader::*;
+use crate::utils::*;
+use crate::writer::*;
+use edflib_sys::*;
+#[cfg(feature = "pure-rust")]
+use std::io::{Cursor, Read, Seek};
+use std::{path::PathBuf, time::Duration};
+
+/// Reads EDF/EDF+/BDF/BDF+ files.
+///
+/// The header is read when the file is opened and exposed through `header`, using the same
+/// model as `OpenEDFWriter`. The file is closed when the reader is dropped.
+pub struct EDFReader {
+    pub file_path: PathBuf,
+    pub header: EDFHeader,
+    file_duration: Duration,
+    datarecord_duration: Duration,
+    datarecords_in_file: i64,
+    annotations_in_file: i64,
+    samples_in_file: Vec<i64>,
+    samples_in_datarecord: Vec<i32>,
+    file: Box<dyn ReaderBackend>,
+}
+
+/// Converts a value expressed in edflib's time unit (100 nanoseconds) into a `Duration`.
+pub(crate) fn from_edflib_time(value: i64) -> Duration {
+    Duration::from_nanos(value.max(0) as u64 * 100)
+}
+
+impl EDFReader {
+    /// Opens an existing EDF/EDF+/BDF/BDF+ file and reads its header.
+    ///
+    /// Annotations are read up to the one with the description "Recording ends",
+    /// see [`EDFReader::open_with_annotations`] to change this.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - A `PathBuf` that points to the file to read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file does not exist, cannot be read or contains format errors.
+    pub fn open(file_path: PathBuf) -> Result<Self> {
+        Self::open_with_annotations(file_path, ReadAnnotations::UntilRecordingEnds)
+    }
+
+    /// Opens an existing EDF/EDF+/BDF/BDF+ file and reads its header and the selected annotations.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - A `PathBuf` that points to the file to read.
+    /// * `read_annotations` - Which annotations edflib reads while opening the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file does not exist, cannot be read or contains format errors.
+    pub fn open_with_annotations(
+        file_path: PathBuf,
+        read_annotations: ReadAnnotations,
+    ) -> Result<Self> {
+        let (edf, hdr) = Edf::open_file_readonly(file_path.clone(), read_annotations)?;
+
+        let format = edf.format();
+        let is_plus = format.is_plus();
+        // 标准 EDF/BDF 没有结构化的患者信息，只有原始的 patient/recording 字段
+        let patient_info = if is_plus {
+            EDFPatientInfo {
+                patient_name: chars_to_string(&hdr.patient_name),
+                patient_code: chars_to_string(&hdr.patientcode),
+                sex: match chars_to_string(&hdr.sex).as_str() {
+                    "Male" => 1,
+                    "Female" => 0,
+                    _ => -1,
+                },
+                // birthdate_year 为 0 表示未填写
+                birthdate: (hdr.birthdate_year != 0).then(|| {
+                    EDFDate::new(
+                        hdr.birthdate_year,
+                        hdr.birthdate_month as u32,
+                        hdr.birthdate_day as u32,
+                    )
+                }),
+                patient_additional: chars_to_string(&hdr.patient_additional),
+                admin_code: chars_to_string(&hdr.admincode),
+                technician: chars_to_string(&hdr.technician),
+                equipment: chars_to_string(&hdr.equipment),
+                recording_additional: chars_to_string(&hdr.recording_additional),
+            }
+        } else {
+            EDFPatientInfo {
+                patient_name: chars_to_string(&hdr.patient),
+                patient_code: String::new(),
+                sex: -1,
+                birthdate: None,
+                patient_additional: String::new(),
+                admin_code: String::new(),
+                technician: String::new(),
+                equipment: chars_to_string(&hdr.recording),
+                recording_additional: String::new(),
+            }
+        };
+
+        let signal_count = hdr.edfsignals.max(0) as usize;
+        let signals = &hdr.signalparam[..signal_count];
+        let channels = signals
+            .iter()
+            .map(|param| EDFChannel {
+                label: chars_to_string(&param.label),
+                transducer: chars_to_string(&param.transducer),
+                digital_max: param.dig_max,
+                digital_min: param.dig_min,
+                physical_max: param.phys_max,
+                physical_min: param.phys_min,
+                physical_dimension: chars_to_string(&param.physdimension),
+                prefilter: chars_to_string(&param.prefilter),
+                sample_frequency: param.smp_in_datarecord as f64 * EDFLIB_TIME_DIMENSION as f64
+                    / hdr.datarecord_duration as f64,
+            })
+            .collect();
+
+        Ok(Self {
+            file_path,
+            header: EDFHeader {
+                patient_info,
+                channels,
+                start_datetime: Some(
+                    EDFDateTime::new(
+                        EDFDate::new(
+                            hdr.startdate_year,
+                            hdr.startdate_month as u32,
+                            hdr.startdate_day as u32,
+                        ),
+                        hdr.starttime_hour as u32,
+                        hdr.starttime_minute as u32,
+                        hdr.starttime_second as u32,
+                    )
+                    .with_nanosecond(hdr.starttime_subsecond as u32 * 100),
+                ),
+                datarecord_duration: Some(from_edflib_time(hdr.datarecord_duration)),
+                annotation_channels: None,
+                annotation_position: None,
+                file_format: Some(format),
+            },
+            file_duration: from_edflib_time(hdr.file_duration),
+            datarecord_duration: from_edflib_time(hdr.datarecord_duration),
+            datarecords_in_file: hdr.datarecords_in_file,
+            annotations_in_file: hdr.annotations_in_file,
+            samples_in_file: signals.iter().map(|param| param.smp_in_file).collect(),
+            samples_in_datarecord: signals
+                .iter()
+                .map(|param| param.smp_in_datarecord)
+                .collect(),
+            file: Box::new(edf),
+        })
+    }
+
+    /// Reads an EDF/EDF+/BDF/BDF+ file from `reader` without edflib, e.g. from memory or an archive.
+    ///
+    /// `reader` holds a whole file starting at offset 0. Annotations are read up to the one with
+    /// the description "Recording ends", see [`EDFReader::from_reader_with_annotations`] to change
+    /// this. `file_path` is empty. Only available with the `pure-rust` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EdfError::InvalidHeader`] if the header is malformed or the size of the file does
+    /// not match it, [`EdfError::FileIsDiscontinuous`] for EDF+D/BDF+D files,
+    /// [`EdfError::FileContainsFormatErrors`] if the annotations are malformed and
+    /// [`EdfError::FileReadError`] if `reader` fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::io::Cursor;
+    /// # use edflib::EDFReader;
+    /// # fn main() -> edflib::Result<()> {
+    /// # let downloaded: Vec<u8> = Vec::new();
+    /// let mut reader = EDFReader::from_reader(Cursor::new(downloaded))?;
+    /// let mut buf = vec![0.0; 256];
+    /// reader.read_physical_samples(0, &mut buf)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "pure-rust")]
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self> {
+        Self::from_reader_with_annotations(reader, ReadAnnotations::UntilRecordingEnds)
+    }
+
+    /// Reads an EDF/EDF+/BDF/BDF+ file from `reader` without edflib, and the selected annotations.
+    ///
+    /// See [`EDFReader::from_reader`].
+    #[cfg(feature = "pure-rust")]
+    pub fn from_reader_with_annotations<R: Read + Seek + Send + 'static>(
+        reader: R,
+        read_annotations: ReadAnnotations,
+    ) -> Result<Self> {
+        Self::open_native(reader, "from_reader", read_annotations)
+    }
+
+    /// Reads an EDF/EDF+/BDF/BDF+ file from its contents without edflib.
+    ///
+    /// The bytes are copied; pass a `Cursor<Vec<u8>>` to [`EDFReader::from_reader`] to hand over
+    /// a buffer without copying it. See [`EDFReader::from_reader`].
+    #[cfg(feature = "pure-rust")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::open_native(
+            Cursor::new(bytes.to_vec()),
+            "from_bytes",
+            ReadAnnotations::UntilRecordingEnds,
+        )
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn open_native<R: Read + Seek + Send + 'static>(
+        reader: R,
+        operation: &'static str,
+        read_annotations: ReadAnnotations,
+    ) -> Result<Self> {
+        let (file, parsed) = NativeReader::open(reader, operation, read_annotations)?;
+        let samples_in_datarecord: Vec<i32> = parsed
+            .signals
+            .iter()
+            .filter(|signal| !signal.annotation)
+            .map(|signal| signal.samples_in_datarecord as i32)
+            .collect();
+
+        Ok(Self {
+            file_path: PathBuf::new(),
+            header: parsed.header,
+            file_duration: parsed.datarecord_duration * parsed.datarecords_in_file as u32,
+            datarecord_duration: parsed.datarecord_duration,
+            datarecords_in_file: parsed.datarecords_in_file,
+            annotations_in_file: file.annotations_in_file(),
+            samples_in_file: samples_in_datarecord
+                .iter()
+                .map(|&samples| samples as i64 * parsed.datarecords_in_file)
+                .collect(),
+            samples_in_datarecord,
+            file: Box::new(file),
+        })
+    }
+
+    /// Returns the duration of the recording.
+    pub fn file_duration(&self) -> Duration {
+        self.file_duration
+    }
+
+    /// Returns the duration of a single datarecord.
+    pub fn datarecord_duration(&self) -> Duration {
+        self.datarecord_duration
+    }
+
+    /// Returns the number of datarecords in the file.
+    pub fn datarecords_in_file(&self) -> i64 {
+        self.datarecords_in_file
+    }
+
+    /// Returns the number of annotations read when the file was opened.
+    pub fn annotations_in_file(&self) -> i64 {
+        self.annotations_in_file
+    }
+
+    /// Returns an iterator over the annotations read when the file was opened.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use edflib::{EDFReader, ReadAnnotations};
+    /// # fn main() -> edflib::Result<()> {
+    /// let reader = EDFReader::open_with_annotations("recording.edf".into(), ReadAnnotations::All)?;
+    /// for annotation in reader.annotations() {
+    ///     let annotation = annotation?;
+    ///     println!("{:?} {}", annotation.onset, annotation.description);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn annotations(&self) -> Annotations<'_> {
+        Annotations {
+            file: self.file.as_ref(),
+            next: 0,
+            count: self.annotations_in_file,
+        }
+    }
+
+    /// Returns the total number of samples of `channel` in the file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn samples_in_file(&self, channel: usize) -> i64 {
+        self.samples_in_file[channel]
+    }
+
+    /// Returns the number of samples of `channel` in a single datarecord.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn samples_in_datarecord(&self, channel: usize) -> i32 {
+        self.samples_in_datarecord[channel]
+    }
+
+    /// Reads physical samples (e.g. uV) of a channel into `buf`.
+    ///
+    /// Reading starts at the channel's current sample position, which is advanced by the number
+    /// of samples read. Every channel keeps its own position.
+    ///
+    /// # Returns
+    ///
+    /// The number of samples read; this is less than `buf.len()` (possibly zero) at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `channel` is out of range or edflib fails to read the samples.
+    pub fn read_physical_samples(&mut self, channel: usize, buf: &mut [f64]) -> Result<usize> {
+        let edfsignal = self.check_channel(channel, "read_physical_samples")?;
+        self.file.read_physical_samples(edfsignal, buf)
+    }
+
+    /// Reads digital (raw ADC) samples of a channel into `buf`.
+    ///
+    /// Behaves like [`EDFReader::read_physical_samples`] but skips the conversion to physical units.
+    pub fn read_digital_samples(&mut self, channel: usize, buf: &mut [i32]) -> Result<usize> {
+        let edfsignal = self.check_channel(channel, "read_digital_samples")?;
+        self.file.read_digital_samples(edfsignal, buf)
+    }
+
+    /// Returns a seekable cursor over the samples of `channel`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `channel` is out of range.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use edflib::EDFReader;
+    /// # fn main() -> edflib::Result<()> {
+    /// let reader = EDFReader::open("recording.edf".into())?;
+    /// let mut cursor = reader.cursor(0)?;
+    /// // 读取第 2 小时开始的 10 秒数据
+    /// let window = cursor.read_window(Duration::from_secs(7200), Duration::from_secs(10))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cursor(&self, channel: usize) -> Result<SignalCursor<'_>> {
+        let edfsignal = self.check_channel(channel, "cursor")?;
+        Ok(SignalCursor::new(
+            self.file.as_ref(),
+            edfsignal,
+            self.samples_in_file[channel],
+            self.samples_in_datarecord[channel],
+            self.datarecord_duration,
+        ))
+    }
+
+    fn check_channel(&self, channel: usize, operation: &'static str) -> Result<i32> {
+        if channel >= self.header.channels.len() {
+            return Err(EdfError::invalid_argument(
+                ErrorContext::new(operation, Some(channel), self.file_path.clone()),
+                format!(
+                    "channel out of range, the file has {} channels",
+                    self.header.channels.len()
+                ),
+            ));
+        }
+        Ok(channel as i32)
+    }
+}
+
+/// Iterator over the annotations of an `EDFReader`, created by [`EDFReader::annotations`].
+pub struct Annotations<'a> {
+    file: &'a dyn ReaderBackend,
+    next: i64,
+    count: i64,
+}
+
+impl Iterator for Annotations<'_> {
+    type Item = Result<EDFAnnotation>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.count {
+            return None;
+        }
+        let annot = self.file.annotation(self.next);
+        self.next += 1;
+        Some(annot)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Annotations<'_> {}
@@ -0,0 +1,135 @@
+use crate::error::*;
+use crate::writer::*;
+use std::{
+    panic,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+/// Work queued for the writer thread
+enum Command {
+    Frame(Vec<Vec<f64>>),
+    Push(usize, Vec<f64>),
+    Annotation(EDFAnnotation),
+}
+
+/// Writes an EDF file on a dedicated thread, fed through a bounded queue.
+///
+/// The queue holds at most `capacity` frames, pushes and annotations; the methods block while it
+/// is full, so a slow disk slows down the producers instead of filling up memory. The writer
+/// thread stops at the first error, after which the methods return
+/// [`EdfError::WriterThreadStopped`] and [`ThreadedEDFWriter::finish`] returns the error itself.
+///
+/// `ThreadedEDFWriter` is `Send` and `Sync`, so several producer threads can share it.
+///
+/// # Example
+///
+/// ```no_run
+/// # use edflib::{EDFHeader, EDFWriterBuilder, ThreadedEDFWriter};
+/// # fn write(header: EDFHeader) -> edflib::Result<()> {
+/// let writer = EDFWriterBuilder::from_header("output.edf".into(), header).open()?;
+/// let writer = ThreadedEDFWriter::spawn(writer, 16);
+/// writer.write_frame(vec![vec![0.0; 256], vec![0.0; 256]])?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ThreadedEDFWriter {
+    file_path: PathBuf,
+    sender: Option<SyncSender<Command>>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl ThreadedEDFWriter {
+    /// Moves `writer` to a new thread that writes what is queued, up to `capacity` items.
+    pub fn spawn(mut writer: OpenEDFWriter, capacity: usize) -> Self {
+        let file_path = writer.file_path.clone();
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let handle = thread::spawn(move || {
+            let result = Self::run(&mut writer, receiver);
+            // 出错时也要关闭文件，返回先发生的错误
+            let closed = writer.finish();
+            result.and(closed)
+        });
+
+        Self {
+            file_path,
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    fn run(writer: &mut OpenEDFWriter, receiver: Receiver<Command>) -> Result<()> {
+        for command in receiver {
+            match command {
+                Command::Frame(channel_samples) => writer.write_sample_stream(&channel_samples)?,
+                Command::Push(channel, samples) => writer.push(channel, &samples)?,
+                Command::Annotation(annotation) => writer.write_edf_annotation(&annotation)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues a frame for [`OpenEDFWriter::write_sample_stream`], blocking while the queue is full.
+    pub fn write_frame(&self, channel_samples: Vec<Vec<f64>>) -> Result<()> {
+        self.send(Command::Frame(channel_samples), "write_frame")
+    }
+
+    /// Queues samples for [`OpenEDFWriter::push`], blocking while the queue is full.
+    pub fn push(&self, channel: usize, samples: Vec<f64>) -> Result<()> {
+        self.send(Command::Push(channel, samples), "push")
+    }
+
+    /// Queues an annotation for [`OpenEDFWriter::write_edf_annotation`], blocking while the queue is full.
+    pub fn write_annotation(&self, annotation: EDFAnnotation) -> Result<()> {
+        self.send(Command::Annotation(annotation), "write_annotation")
+    }
+
+    fn send(&self, command: Command, operation: &'static str) -> Result<()> {
+        let sent = match &self.sender {
+            Some(sender) => sender.send(command).is_ok(),
+            None => false,
+        };
+        if sent {
+            Ok(())
+        } else {
+            Err(EdfError::WriterThreadStopped {
+                context: ErrorContext::new(operation, None, self.file_path.clone()),
+            })
+        }
+    }
+
+    /// Writes everything still queued, closes the file and waits for the writer thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the writer thread, including errors closing the file
+    /// (see [`OpenEDFWriter::finish`]).
+    ///
+    /// # Panics
+    ///
+    /// Resumes the panic if the writer thread panicked.
+    pub fn finish(mut self) -> Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<()> {
+        // 关闭队列，写入线程处理完剩余的数据后退出
+        self.sender = None;
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ThreadedEDFWriter {
+    /// Waits for the writer thread like [`ThreadedEDFWriter::finish`], ignoring errors.
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = self.join();
+        }
+    }
+}
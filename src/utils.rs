@@ -1,11 +1,72 @@
-use std::{ ffi::{ CStr, CString }, os::raw::c_char };
+use std::{ffi::CString, os::raw::c_char, path::Path};
 
-pub fn str_to_char(input: &str) -> *const c_char {
-    CString::new(input).unwrap().into_raw()
+/// Encoding of text passed to edflib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// ISO 8859-1, used for header fields which edflib reduces to ASCII
+    Latin1,
+    /// UTF-8, used for annotations
+    Utf8,
 }
 
-pub fn char_to_str(ptr: *mut i8) -> String {
-    let cstr = unsafe { CStr::from_ptr(ptr) };
-    let result = cstr.to_str().unwrap().to_owned().to_string();
-    result
+/// Converts `text` into a NUL-terminated string for edflib of at most `max_len` bytes.
+///
+/// The returned `CString` owns the memory, so it must be kept alive while edflib uses the pointer.
+/// Text longer than `max_len` is cut at a character boundary if `truncate` is set.
+///
+/// # Errors
+///
+/// Returns a description of the problem if `text` contains a NUL character, cannot be encoded
+/// or is too long and `truncate` is not set.
+pub fn to_cstring(
+    text: &str,
+    encoding: TextEncoding,
+    max_len: usize,
+    truncate: bool,
+) -> Result<CString, String> {
+    let mut bytes = match encoding {
+        TextEncoding::Latin1 => text
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| format!("'{}' cannot be encoded as Latin-1", c)))
+            .collect::<Result<Vec<u8>, String>>()?,
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+    };
+
+    if bytes.len() > max_len {
+        if !truncate {
+            return Err(format!(
+                "text is {} bytes long, at most {} bytes are allowed",
+                bytes.len(),
+                max_len
+            ));
+        }
+        // Latin-1 每个字符占一个字节，UTF-8 需要在字符边界处截断
+        let len = match encoding {
+            TextEncoding::Latin1 => max_len,
+            TextEncoding::Utf8 => (0..=max_len)
+                .rev()
+                .find(|&len| text.is_char_boundary(len))
+                .unwrap_or(0),
+        };
+        bytes.truncate(len);
+    }
+
+    CString::new(bytes).map_err(|_| "text contains a NUL character".to_string())
+}
+
+/// Converts a path into a NUL-terminated string for edflib, `None` if it contains a NUL character.
+pub fn path_to_cstring(path: &Path) -> Option<CString> {
+    CString::new(path.as_os_str().as_encoded_bytes()).ok()
+}
+
+/// Converts a fixed-size, null-terminated `char` array filled in by edflib into a `String`.
+///
+/// Invalid UTF-8 is replaced rather than rejected and trailing padding spaces are removed.
+pub fn chars_to_string(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
 }
@@ -1,16 +1,46 @@
+use crate::backend::*;
 use crate::base::*;
-use anyhow::Result;
-use std::{f64::consts::PI, path::PathBuf};
+use crate::datetime::*;
+use crate::error::*;
+#[cfg(feature = "pure-rust")]
+use crate::native::*;
+use crate::utils::*;
+#[cfg(feature = "pure-rust")]
+use std::io::{Seek, Write};
+use std::{path::PathBuf, time::Duration};
 /// Used to store patient information, record instrument information, etc.
 #[derive(Debug, Clone)]
 pub struct EDFPatientInfo {
     pub patient_name: String,
     pub patient_code: String,
-    /// 0 表示 female, 1 表示 male
+    /// 0 表示 female, 1 表示 male, -1 表示 unknown (left empty when writing)
     pub sex: i32,
+    /// Birthdate of the patient, omitted from the header if `None`
+    pub birthdate: Option<EDFDate>,
+    /// Additional patient information, e.g. medication
+    pub patient_additional: String,
     pub admin_code: String,
     pub technician: String,
     pub equipment: String,
+    /// Additional recording information, e.g. the montage or the study protocol
+    pub recording_additional: String,
+}
+
+impl Default for EDFPatientInfo {
+    /// Empty fields and an unknown sex.
+    fn default() -> Self {
+        Self {
+            patient_name: String::new(),
+            patient_code: String::new(),
+            sex: -1,
+            birthdate: None,
+            patient_additional: String::new(),
+            admin_code: String::new(),
+            technician: String::new(),
+            equipment: String::new(),
+            recording_additional: String::new(),
+        }
+    }
 }
 
 /// Used to store metadata information for a single channel
@@ -23,120 +53,434 @@ pub struct EDFChannel {
     pub physical_max: f64,
     pub physical_min: f64,
     pub physical_dimension: String,
-    pub sample_frequency: i32,
+    /// Filtering applied to the signal, e.g. "HP:0.1Hz LP:75Hz N:50Hz"
+    pub prefilter: String,
+    /// Samples per second, may be fractional (e.g. 0.5 Hz)
+    pub sample_frequency: f64,
 }
 
 /// Used to store annotations for EDF/BDF files
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EDFAnnotation {
-    pub onset: i32,    // in micosecs
-    pub duration: i32, // in micosecs
+    /// Time of the event relative to the start of the recording
+    pub onset: Duration,
+    /// Duration of the event, `None` if it is unknown or not applicable
+    pub duration: Option<Duration>,
+    /// UTF-8 description of the event
     pub description: String,
 }
 
+/// Longest datarecord duration in seconds
+const MAX_DATARECORD_SECONDS: u64 = 60;
+
+/// Shortest datarecord duration
+const MIN_DATARECORD_DURATION: Duration = Duration::from_micros(1);
+
+/// Most samples held back while the number of annotation channels is chosen, 64 MiB of `f64`
+const MAX_HELD_SAMPLES: usize = 8 * 1024 * 1024;
+
+/// Whole datarecords held back until the number of annotation channels is chosen
+enum HeldRecords {
+    Physical(Vec<f64>),
+    Digital(Vec<i32>),
+}
+
+/// What `OpenEDFWriter::finish` does with samples passed to [`OpenEDFWriter::push`] that do not fill a
+/// whole datarecord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FinalRecordPolicy {
+    /// Drop the incomplete datarecord
+    Discard,
+    /// Complete the datarecord with physical zeros
+    #[default]
+    PadWithZeros,
+    /// Complete the datarecord by repeating the last buffered sample of each channel,
+    /// channels without buffered samples are completed with physical zeros
+    PadWithLastSample,
+}
+
 /// Used to store header information, contains multiple channels
 #[derive(Debug, Clone)]
 pub struct EDFHeader {
     pub patient_info: EDFPatientInfo,
     pub channels: Vec<EDFChannel>,
+    /// Start date and time of the recording, edflib uses the current system time if `None` (the
+    /// pure-Rust backend the current UTC time).
+    /// The fraction of the second is stored with a resolution of 100 ns.
+    pub start_datetime: Option<EDFDateTime>,
+    /// Duration of a datarecord, chosen when the file is opened if `None`.
+    ///
+    /// Durations from 1 µs to 10 ms have a resolution of 1 µs, longer ones up to 60 s a
+    /// resolution of 10 µs. Every channel must have a whole number of samples per datarecord.
+    pub datarecord_duration: Option<Duration>,
+    /// Number of annotation channels, from 1 to [`MAX_ANNOTATION_CHANNELS`].
+    ///
+    /// Every annotation channel holds one annotation per datarecord. If `None`, the writer
+    /// holds the datarecords back until [`OpenEDFWriter::finish`] and then uses enough channels for
+    /// all annotations. Once 64 MiB of samples are held, the channels are sized from the
+    /// annotations per datarecord so far and the datarecords are written.
+    pub annotation_channels: Option<usize>,
+    /// Position of the annotation channels among the signals, edflib puts them at the end if `None`
+    pub annotation_position: Option<AnnotationPosition>,
+    /// Format of the file, EDF+ or BDF+ selected by the extension of the path if `None`.
+    ///
+    /// A ".edf" or ".bdf" extension must match the format. Plain EDF and BDF have no annotations
+    /// and no subsecond start time; edflib cannot write them, so they are always written by the
+    /// pure-Rust backend and need the default `pure-rust` feature.
+    pub file_format: Option<FileFormat>,
 }
 
-pub struct EDFWriter {
-    pub file_path: PathBuf,
-    pub header: EDFHeader,
-    edf: Option<Edf>,
+/// Integer types that can be written as digital (raw ADC) samples by `OpenEDFWriter`.
+///
+/// `i16` covers the 16-bit samples of EDF, `i32` additionally covers the 24-bit samples of BDF.
+/// Samples are converted to `i32` and range-checked before any backend writes them.
+pub trait DigitalSample: Copy + Into<i32> {}
+
+impl DigitalSample for i16 {}
+
+impl DigitalSample for i32 {}
+
+/// Writes the samples and annotations of a file opened by
+/// [`EDFWriterBuilder`](crate::EDFWriterBuilder).
+///
+/// The header is fixed once the file is open: edflib ignores header changes after the first
+/// samples, so the writer only writes samples and annotations. [`OpenEDFWriter::finish`] closes
+/// the file.
+pub struct OpenEDFWriter {
+    pub(crate) file_path: PathBuf,
+    pub(crate) header: EDFHeader,
+    datarecord_duration: Duration,
+    samples_in_datarecord: Vec<usize>,
+    /// Samples passed to `push` that do not fill a datarecord yet, one buffer per channel
+    pending: Vec<Vec<f64>>,
+    final_record_policy: FinalRecordPolicy,
+    overlong_text: OverlongText,
+    format: FileFormat,
+    /// Number of annotation channels, `None` until it is chosen from the annotations
+    annotation_channels: Option<usize>,
+    /// Datarecords written while `annotation_channels` is `None`
+    held: Vec<HeldRecords>,
+    held_samples: usize,
+    annotations_written: usize,
+    /// Number of datarecords written, including the held ones
+    datarecords_written: usize,
+    /// Number of datarecords declared by `EDFWriterBuilder::open_stream`
+    #[cfg(feature = "pure-rust")]
+    stream_datarecords: Option<usize>,
+    backend: Backend,
+    /// `None` when writing to `file_path`
+    #[cfg(feature = "pure-rust")]
+    sink: Option<Sink>,
+    file: Option<Box<dyn WriterBackend>>,
 }
 
-impl EDFWriter {
-    /// Creates a new `EDFWriter` instance.
-    ///
-    /// # Arguments
-    ///
-    /// * `file_path` - A `PathBuf` that points to the file location where the EDF should be written.
-    /// * `header` - An `EDFHeader` containing metadata such as channel information.
-    pub fn new(file_path: PathBuf, header: EDFHeader) -> Self {
+impl OpenEDFWriter {
+    /// Creates a writer for `file_path`, which [`OpenEDFWriter::open`] creates.
+    pub(crate) fn new(file_path: PathBuf, header: EDFHeader) -> Self {
         Self {
             file_path,
             header,
-            edf: None,
+            datarecord_duration: Duration::from_secs(1),
+            samples_in_datarecord: Vec::new(),
+            pending: Vec::new(),
+            final_record_policy: FinalRecordPolicy::default(),
+            overlong_text: OverlongText::default(),
+            format: FileFormat::EdfPlus,
+            annotation_channels: None,
+            held: Vec::new(),
+            held_samples: 0,
+            annotations_written: 0,
+            datarecords_written: 0,
+            #[cfg(feature = "pure-rust")]
+            stream_datarecords: None,
+            backend: Backend::default(),
+            #[cfg(feature = "pure-rust")]
+            sink: None,
+            file: None,
+        }
+    }
+
+    /// Creates a writer that writes to `sink` instead of `file_path`.
+    #[cfg(feature = "pure-rust")]
+    pub(crate) fn with_sink<W: Write + Seek + Send + 'static>(
+        file_path: PathBuf,
+        sink: W,
+        header: EDFHeader,
+    ) -> Self {
+        let create: CreateSink = Box::new(move |settings: &WriterSettings| {
+            Ok(Box::new(NativeWriter::new(sink, settings)?) as Box<dyn WriterBackend>)
+        });
+        Self {
+            sink: Some(Sink::Unopened(create)),
+            ..Self::new(file_path, header)
+        }
+    }
+
+    /// Creates a writer that writes `datarecords` datarecords to a sink that cannot seek.
+    #[cfg(feature = "pure-rust")]
+    pub(crate) fn with_stream<W: Write + Send + 'static>(
+        file_path: PathBuf,
+        sink: W,
+        header: EDFHeader,
+        datarecords: usize,
+    ) -> Self {
+        let create: CreateSink = Box::new(move |settings: &WriterSettings| {
+            let writer = NativeWriter::stream(sink, settings, datarecords)?;
+            Ok(Box::new(writer) as Box<dyn WriterBackend>)
+        });
+        Self {
+            sink: Some(Sink::Unopened(create)),
+            stream_datarecords: Some(datarecords),
+            ..Self::new(file_path, header)
+        }
+    }
+
+    /// Returns the sink of a writer opened by
+    /// [`EDFWriterBuilder::open_sink`](crate::EDFWriterBuilder::open_sink) or
+    /// [`EDFWriterBuilder::open_stream`](crate::EDFWriterBuilder::open_stream) after
+    /// [`OpenEDFWriter::finish`], also if finishing failed.
+    ///
+    /// Returns `None` if the writer has no sink, has not been finished or `W` is not the type of
+    /// the sink.
+    #[cfg(feature = "pure-rust")]
+    pub fn into_sink<W: 'static>(self) -> Option<W> {
+        let Some(Sink::Finished(sink)) = self.sink else {
+            return None;
+        };
+        match sink.downcast::<W>() {
+            Ok(sink) => Some(*sink),
+            // 流式写入的输出被包装过
+            Err(sink) => sink.downcast::<Unseekable<W>>().ok().map(|sink| sink.0),
         }
     }
 
-    /// Opens the EDF file for writing and initializes it with the header information.
+    /// Checks the header, creates the file and writes the header settings.
+    pub(crate) fn open(&mut self) -> Result<()> {
+        let channel_count = self.header.channels.len();
+
+        // 在创建文件之前先检查头信息
+        let (datarecord_duration, samples_in_datarecord, format) = self.check_header()?;
+        // 创建文件并设置通道及其他头信息
+        let settings = WriterSettings {
+            file_path: &self.file_path,
+            header: &self.header,
+            format,
+            datarecord_duration,
+            samples_in_datarecord: &samples_in_datarecord,
+            overlong_text: self.overlong_text,
+        };
+        #[cfg(feature = "pure-rust")]
+        let file = match self.sink.take() {
+            None => self.backend.create(&settings)?,
+            Some(Sink::Unopened(create)) => {
+                self.sink = Some(Sink::Open);
+                create(&settings)?
+            }
+            Some(sink) => {
+                self.sink = Some(sink);
+                return Err(EdfError::invalid_argument(
+                    self.context("open"),
+                    "the sink has already been written to",
+                ));
+            }
+        };
+        #[cfg(not(feature = "pure-rust"))]
+        let file = self.backend.create(&settings)?;
+        self.datarecord_duration = datarecord_duration;
+        self.samples_in_datarecord = samples_in_datarecord;
+        self.pending = vec![Vec::new(); channel_count];
+        self.format = format;
+        // 标准 EDF/BDF 没有注释信号
+        self.annotation_channels = match self.header.annotation_channels {
+            _ if !format.is_plus() => Some(0),
+            Some(channels) => Some(channels),
+            None => None,
+        };
+        self.held.clear();
+        self.held_samples = 0;
+        self.annotations_written = 0;
+        self.datarecords_written = 0;
+
+        self.file = Some(file);
+        if let Some(channels @ 1..) = self.annotation_channels {
+            self.file_mut("open")?
+                .set_number_of_annotation_signals(channels)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the duration of a datarecord, chosen when the file was opened.
+    ///
+    /// Unless `header.datarecord_duration` is set, it is the shortest whole number of seconds in
+    /// which every channel has a whole number of samples, e.g. 1 s for integer sample frequencies
+    /// and 2 s if a channel is sampled at 0.5 Hz.
+    pub fn datarecord_duration(&self) -> Duration {
+        self.datarecord_duration
+    }
+
+    /// Returns the number of samples of `channel` in a single datarecord.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn samples_in_datarecord(&self, channel: usize) -> usize {
+        self.samples_in_datarecord[channel]
+    }
+
+    /// See `EDFWriterBuilder::final_record_policy`.
+    pub(crate) fn set_final_record_policy(&mut self, policy: FinalRecordPolicy) {
+        self.final_record_policy = policy;
+    }
+
+    /// See `EDFWriterBuilder::overlong_text`.
+    pub(crate) fn set_overlong_text(&mut self, overlong_text: OverlongText) {
+        self.overlong_text = overlong_text;
+    }
+
+    /// See `EDFWriterBuilder::backend`.
+    pub(crate) fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Appends samples of a single channel, in any amount.
+    ///
+    /// The samples are buffered per channel; as soon as every channel has enough samples for a
+    /// datarecord it is written to the file. An incomplete last datarecord is handled by
+    /// [`OpenEDFWriter::finish`] according to the [`FinalRecordPolicy`].
+    ///
+    /// While samples are buffered, the methods that write whole datarecords return an error.
     ///
     /// # Errors
     ///
-    /// Returns an error if the file cannot be opened or if there is a problem
-    /// setting up the header in the file.
+    /// Returns an error if the file has been finished, if `channel` is out of range, if a sample
+    /// is NaN or if writing fails.
     ///
     /// # Example
     ///
+    /// ```no_run
+    /// # use edflib::OpenEDFWriter;
+    /// # fn writer() -> OpenEDFWriter { unimplemented!() }
+    /// # fn main() -> edflib::Result<()> {
+    /// let mut writer = writer();
+    /// // 设备按不规则的数据包上报采样点
+    /// writer.push(0, &[0.0; 17])?;
+    /// writer.push(1, &[0.0; 40])?;
+    /// writer.finish()?;
+    /// # Ok(())
+    /// # }
     /// ```
-    /// # use std::path::PathBuf;
-    /// # use your_crate::{EDFWriter, EDFHeader};
-    /// # let mut writer = EDFWriter::new(PathBuf::from("path/to/your/file.edf"), EDFHeader::new());
-    /// match writer.open() {
-    ///     Ok(_) => println!("File opened successfully"),
-    ///     Err(e) => println!("Failed to open file: {}", e),
-    /// }
-    /// ```
-    pub fn open(&mut self) -> Result<()> {
-        let channel_count = self.header.channels.len();
-        let mut edf = Edf::new(self.file_path.clone(), channel_count as i32);
-
-        edf.open_file_writeonly()?;
-        // 设置通道及其他头信息
-        self.setup_header(&mut edf)?;
+    pub fn push(&mut self, channel: usize, samples: &[f64]) -> Result<()> {
+        if self.file.is_none() {
+            return Err(EdfError::NotOpen {
+                context: self.context("push"),
+            });
+        }
+        if channel >= self.pending.len() {
+            return Err(EdfError::invalid_argument(
+                ErrorContext::new("push", Some(channel), self.file_path.clone()),
+                format!(
+                    "channel out of range, the file has {} channels",
+                    self.header.channels.len()
+                ),
+            ));
+        }
+        // 在缓冲之前检查，出错时不保留任何采样点
+        if samples.iter().any(|value| value.is_nan()) {
+            return Err(self.nan_error(channel, "push"));
+        }
+        self.pending[channel].extend_from_slice(samples);
+        self.flush_pending()
+    }
 
-        self.edf = Some(edf);
-        Ok(())
+    /// Returns the number of samples of `channel` buffered by [`OpenEDFWriter::push`] and not yet written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn pending_samples(&self, channel: usize) -> usize {
+        self.pending[channel].len()
     }
 
-    /// Writes a single frame of multi-channel data to the EDF file.
+    /// Writes a single frame, i.e. one datarecord, of multi-channel data to the EDF file.
     ///
     /// This function expects `channel_samples` where the length of the outer `Vec` matches
     /// the number of channels specified in the `header.channels`, and each inner `Vec<f64>`
-    /// contains data points corresponding to the `sample_frequency` of that channel.
+    /// contains the samples of that channel in one datarecord.
     ///
     /// # Arguments
     ///
     /// * `channel_samples` - A vector of vectors containing the sample data for each channel.
     ///   The length of the outer vector must match the number of channels in the header.
-    ///   Each inner vector must contain a number of samples that matches the sample frequency
-    ///   of the corresponding channel, unless handled otherwise.
+    ///   Each inner vector must contain [`OpenEDFWriter::samples_in_datarecord`] samples, which is
+    ///   `sample_frequency` for integer sample frequencies.
     ///
     /// # Errors
     ///
-    /// Returns an error if the length of `channel_samples` does not match the number of channels,
-    /// or if writing the data to the file fails.
+    /// Returns an error if the number of channels or samples does not match the header, if a
+    /// sample is NaN, or if writing the data to the file fails.
     ///
-    pub fn write_sample_stream(&mut self, channel_samples: &Vec<Vec<f64>>) -> Result<()> {
-        if let Some(edf) = &mut self.edf {
-            // 检查通道数量是否匹配
-            if channel_samples.len() != self.header.channels.len() {
-                return Err(anyhow::anyhow!(
-                    "给定的通道数据数量({})与header.channels数量({})不一致！",
-                    channel_samples.len(),
-                    self.header.channels.len()
-                ));
-            }
-            // 依次写入每个通道的数据
-            for (ch_idx, ch_data) in channel_samples.iter().enumerate() {
-                let channel_info = &self.header.channels[ch_idx];
-                if ch_data.len() != channel_info.sample_frequency as usize {
-                    eprintln!(
-                        "警告: 通道{} 数据点数({})与声明的采样点数({})不一致",
-                        ch_idx,
-                        ch_data.len(),
-                        channel_info.sample_frequency
-                    );
-                }
-                edf.write_samples(&mut ch_data.clone(), channel_info.sample_frequency as usize)?;
-            }
-        } else {
-            return Err(anyhow::anyhow!("EDFWriter 尚未打开文件，请先调用 open()。"));
-        }
-        Ok(())
+    pub fn write_sample_stream(&mut self, channel_samples: &[Vec<f64>]) -> Result<()> {
+        self.check_frame(channel_samples, "write_sample_stream")?;
+        // 拼接为一个完整的数据记录，一次写入
+        self.write_physical_records(channel_samples.concat(), "write_sample_stream")
+    }
+
+    /// Writes a single frame of multi-channel digital (raw ADC) data to the file.
+    ///
+    /// The samples are written as they are, without a conversion from physical units.
+    /// EDF files store 16-bit and BDF files 24-bit samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_samples` - The samples of each channel. The length of the outer slice must match
+    ///   the number of channels in the header, each inner `Vec` must hold the samples of one datarecord.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of channels or samples does not match the header, if a
+    /// sample lies outside of its channel's `digital_min..=digital_max`, or if writing fails.
+    pub fn write_digital_sample_stream<T: DigitalSample>(
+        &mut self,
+        channel_samples: &[Vec<T>],
+    ) -> Result<()> {
+        self.check_frame(channel_samples, "write_digital_sample_stream")?;
+        self.write_digital_records(&channel_samples.concat(), "write_digital_sample_stream")
+    }
+
+    /// Writes one or more whole datarecords of physical samples with a single call into edflib per datarecord.
+    ///
+    /// Within a datarecord the samples are channel-major: the samples of the first channel,
+    /// followed by those of the second channel and so on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file has been finished, if the length of `samples` is not a
+    /// multiple of the number of samples in a datarecord, if a sample is NaN, or if writing fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use edflib::OpenEDFWriter;
+    /// # fn writer() -> OpenEDFWriter { unimplemented!() }
+    /// # fn main() -> edflib::Result<()> {
+    /// // 两个通道，每个数据记录各 4 个采样点
+    /// let mut writer = writer();
+    /// writer.write_datarecord(&[0.0, 0.1, 0.2, 0.3, 1.0, 1.1, 1.2, 1.3])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_datarecord(&mut self, samples: &[f64]) -> Result<()> {
+        self.check_records("write_datarecord")?;
+        self.check_datarecord_len(samples.len(), "write_datarecord")?;
+        self.write_physical_records(samples.to_vec(), "write_datarecord")
+    }
+
+    /// Writes one or more whole datarecords of digital (raw ADC) samples.
+    ///
+    /// The layout of `samples` is the same as for [`OpenEDFWriter::write_datarecord`], the checks and
+    /// the handling of BDF files are the same as for [`OpenEDFWriter::write_digital_sample_stream`].
+    pub fn write_digital_datarecord<T: DigitalSample>(&mut self, samples: &[T]) -> Result<()> {
+        self.write_digital_records(samples, "write_digital_datarecord")
     }
 
     /// Writes multiple frames of multi-channel data to the EDF file.
@@ -147,7 +491,7 @@ impl EDFWriter {
     ///
     /// # Parameters
     ///
-    /// * `frames_data` - A mutable reference to a vector of frames, where each frame is a vector of channels,
+    /// * `frames_data` - A slice of frames, where each frame is a vector of channels,
     ///   and each channel is a vector of `f64` representing the data points. The structure is as follows:
     ///   - `frames_data[frame_idx]` represents the data for a specific frame.
     ///   - `frames_data[frame_idx][ch_idx]` contains the data for channel `ch_idx` within that frame.
@@ -159,38 +503,27 @@ impl EDFWriter {
     ///
     /// # Errors
     ///
-    /// This function will return an error if:
+    /// All frames are checked before any of them is written. This function will return
+    /// [`EdfError::InvalidArgument`] if:
     /// - There is a mismatch in the expected number of channels per frame based on the file's header configuration.
     /// - Any frame contains a different number of data points per channel than expected by the channel's sample frequency.
-    /// - The file has not been opened or is otherwise not ready for writing.
+    /// - Any frame contains a NaN value.
     ///
-    pub fn write_multi_frames(&mut self, frames_data: &mut Vec<Vec<Vec<f64>>>) -> Result<()> {
-        if frames_data.is_empty() {
-            return Ok(());
-        }
-
-        let mut previous_frame = frames_data[0].clone();
-
-        for (frame_idx, frame) in frames_data.iter_mut().enumerate() {
-            if frame.len() != previous_frame.len() {
-                eprintln!(
-                    "警告: 第 {} 帧的通道数量与前一帧不一致，使用前一帧的数据进行替换。",
-                    frame_idx
-                );
-                *frame = previous_frame.clone();
-            } else {
-                for (ch_idx, channel_data) in frame.iter_mut().enumerate() {
-                    if channel_data.contains(&f64::NAN) {
-                        eprintln!(
-                            "警告: 第 {} 帧的第 {} 通道包含 NaN 值，使用前一帧的数据进行替换。",
-                            frame_idx, ch_idx
-                        );
-                        *channel_data = previous_frame[ch_idx].clone();
-                    }
+    /// It also returns an error if the file has been finished or writing fails.
+    ///
+    pub fn write_multi_frames(&mut self, frames_data: &[Vec<Vec<f64>>]) -> Result<()> {
+        let operation = "write_multi_frames";
+        // 先检查所有帧，出错时不写入任何数据
+        for frame in frames_data {
+            self.check_frame(frame, operation)?;
+            for (ch_idx, channel_data) in frame.iter().enumerate() {
+                if channel_data.iter().any(|value| value.is_nan()) {
+                    return Err(self.nan_error(ch_idx, operation));
                 }
             }
+        }
+        for frame in frames_data {
             self.write_sample_stream(frame)?;
-            previous_frame = frame.clone();
         }
         Ok(())
     }
@@ -198,14 +531,16 @@ impl EDFWriter {
     /// Writes an annotation to the EDF file.
     ///
     /// This function allows you to add annotations to an EDF file, specifying the onset time,
-    /// duration, and a textual description of the event. It is important that the file must
-    /// be opened with the `open()` method before calling this function.
+    /// duration, and a textual description of the event.
+    ///
+    /// Each annotation channel holds one annotation per datarecord, see
+    /// [`EDFHeader::annotation_channels`].
     ///
     /// # Parameters
     ///
     /// * `onset` - The start time of the annotation in microseconds.
-    /// * `duration` - The duration of the annotation in microseconds.
-    /// * `description` - A `String` that describes the annotation.
+    /// * `duration` - The duration of the annotation in microseconds, `None` if it is unknown or not applicable.
+    /// * `description` - A UTF-8 description of at most [`MAX_WRITE_ANNOTATION_LEN`] bytes.
     ///
     /// # Returns
     ///
@@ -214,50 +549,572 @@ impl EDFWriter {
     ///
     /// # Errors
     ///
-    /// Returns an error if the file has not been opened before this function is called.
+    /// Returns an error if the file has been finished, if it is a plain EDF or BDF file, if
+    /// `onset` is negative or if `description` is too long.
     ///
     pub fn write_annotation(
         &mut self,
         onset: i64,
-        duration: i64,
-        description: String,
+        duration: Option<i64>,
+        description: &str,
     ) -> Result<()> {
-        if let Some(edf) = &mut self.edf {
-            edf.write_annotation(onset, duration, description)?;
-        } else {
-            return Err(anyhow::anyhow!("EDFWriter 尚未打开文件，请先调用 open()。"));
+        if self.file.is_some() && !self.format.is_plus() {
+            return Err(EdfError::invalid_argument(
+                self.context("write_annotation"),
+                format!("{:?} files have no annotations", self.format),
+            ));
         }
+        // edflib 用 -1 表示时长未知
+        self.file_mut("write_annotation")?.write_annotation(
+            onset,
+            duration.unwrap_or(-1),
+            description,
+        )?;
+        self.annotations_written += 1;
         Ok(())
     }
 
+    /// Writes an `EDFAnnotation`, e.g. one read by `EDFReader`.
+    ///
+    /// Onset and duration are written with a resolution of 1 µs, see [`OpenEDFWriter::write_annotation`].
+    pub fn write_edf_annotation(&mut self, annotation: &EDFAnnotation) -> Result<()> {
+        let micros = |time: Duration| i64::try_from(time.as_micros()).unwrap_or(i64::MAX);
+        self.write_annotation(
+            micros(annotation.onset),
+            annotation.duration.map(micros),
+            &annotation.description,
+        )
+    }
+
+    /// Writes the samples still buffered by [`OpenEDFWriter::push`] and closes the file.
+    ///
+    /// # Errors
+    ///
+    /// Besides errors writing or closing the file, returns [`EdfError::AnnotationsDropped`] if
+    /// more annotations were written than the annotation channels could hold. The file is
+    /// closed in any case.
     pub fn finish(&mut self) -> Result<()> {
-        if let Some(edf) = self.edf.take() {
-            edf.finish()?;
+        // 即使写入最后的数据记录失败，也要关闭文件
+        let result = self
+            .flush_final_record()
+            .and_then(|_| self.write_held_records("finish"));
+        let Some(mut file) = self.file.take() else {
+            return result;
+        };
+        let closed = file.finish();
+        #[cfg(feature = "pure-rust")]
+        if self.sink.is_some() {
+            self.sink = file.into_sink().map(Sink::Finished);
+        }
+        closed?;
+        result?;
+
+        // edflib 会静默丢弃放不下的注释；没有数据记录时它为每个注释写入一个数据记录
+        let capacity = match self.datarecords_written {
+            0 => self.annotations_written,
+            datarecords => datarecords * self.annotation_channels.unwrap_or(0),
+        };
+        if self.annotations_written > capacity {
+            return Err(EdfError::AnnotationsDropped {
+                context: self.context("finish"),
+                dropped: self.annotations_written - capacity,
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes whole datarecords of channel-major physical samples, or holds them back while
+    /// the number of annotation channels is not chosen yet.
+    fn write_physical_records(&mut self, samples: Vec<f64>, operation: &'static str) -> Result<()> {
+        // 后端会把 NaN 静默地转换为 0
+        if let Some(index) = samples.iter().position(|value| value.is_nan()) {
+            let mut offset = index % self.samples_per_datarecord();
+            let channel = self
+                .samples_in_datarecord
+                .iter()
+                .position(|&len| {
+                    let found = offset < len;
+                    offset = offset.saturating_sub(len);
+                    found
+                })
+                .unwrap_or_default();
+            return Err(self.nan_error(channel, operation));
+        }
+        self.count_datarecords(samples.len(), operation)?;
+        if self.annotation_channels.is_none() {
+            self.held_samples += samples.len();
+            self.held.push(HeldRecords::Physical(samples));
+            return self.limit_held_records(operation);
+        }
+        let samples_per_datarecord = self.samples_per_datarecord();
+        self.file_mut(operation)?
+            .write_physical_records(&samples, samples_per_datarecord)
+    }
+
+    fn nan_error(&self, channel: usize, operation: &'static str) -> EdfError {
+        EdfError::invalid_argument(
+            ErrorContext::new(operation, Some(channel), self.file_path.clone()),
+            "samples must not be NaN",
+        )
+    }
+
+    /// Adds whole datarecords of `samples` samples to the number of datarecords written.
+    fn count_datarecords(&mut self, samples: usize, operation: &'static str) -> Result<()> {
+        let datarecords = samples / self.samples_per_datarecord();
+        // 留住数据记录时也要尽早发现超出声明的数量
+        #[cfg(feature = "pure-rust")]
+        if let Some(declared) = self.stream_datarecords {
+            if self.datarecords_written + datarecords > declared {
+                return Err(EdfError::invalid_argument(
+                    self.context(operation),
+                    format!("the header declares {} datarecords", declared),
+                ));
+            }
+        }
+        #[cfg(not(feature = "pure-rust"))]
+        let _ = operation;
+        self.datarecords_written += datarecords;
+        Ok(())
+    }
+
+    /// Writes the held datarecords once they exceed [`MAX_HELD_SAMPLES`].
+    fn limit_held_records(&mut self, operation: &'static str) -> Result<()> {
+        if self.held_samples > MAX_HELD_SAMPLES {
+            self.write_held_records(operation)?;
+        }
+        Ok(())
+    }
+
+    /// Chooses the number of annotation channels and writes the held datarecords.
+    ///
+    /// The channels hold the annotations written so far in the datarecords written so far;
+    /// without datarecords edflib writes a datarecord for every annotation.
+    fn write_held_records(&mut self, operation: &'static str) -> Result<()> {
+        if self.annotation_channels.is_some() || self.file.is_none() {
+            return Ok(());
+        }
+        let channels = match self.datarecords_written {
+            0 => 1,
+            datarecords => self
+                .annotations_written
+                .div_ceil(datarecords)
+                .clamp(1, MAX_ANNOTATION_CHANNELS),
+        };
+        self.file_mut(operation)?
+            .set_number_of_annotation_signals(channels)?;
+        self.annotation_channels = Some(channels);
+
+        let samples_per_datarecord = self.samples_per_datarecord();
+        self.held_samples = 0;
+        for records in std::mem::take(&mut self.held) {
+            let file = self.file_mut(operation)?;
+            match records {
+                HeldRecords::Physical(samples) => {
+                    file.write_physical_records(&samples, samples_per_datarecord)?
+                }
+                HeldRecords::Digital(samples) => {
+                    file.write_digital_records(&samples, samples_per_datarecord)?
+                }
+            }
         }
         Ok(())
     }
 
-    fn setup_header(&self, edf: &mut Edf) -> Result<()> {
+    /// Writes every complete datarecord buffered by `push`.
+    fn flush_pending(&mut self) -> Result<()> {
+        while self
+            .pending
+            .iter()
+            .zip(&self.samples_in_datarecord)
+            .all(|(buffer, &len)| buffer.len() >= len)
+        {
+            if self.file.is_none() {
+                return Ok(());
+            }
+            let mut record = Vec::with_capacity(self.samples_per_datarecord());
+            for (buffer, &len) in self.pending.iter_mut().zip(&self.samples_in_datarecord) {
+                record.extend(buffer.drain(..len));
+            }
+            self.write_physical_records(record, "push")?;
+        }
+        Ok(())
+    }
+
+    /// Completes the samples buffered by `push` according to the final record policy and writes them.
+    fn flush_final_record(&mut self) -> Result<()> {
+        if self.pending.iter().all(Vec::is_empty) {
+            return Ok(());
+        }
+        // 不足一个数据记录的部分按策略补齐
+        let records = self
+            .pending
+            .iter()
+            .zip(&self.samples_in_datarecord)
+            .map(|(buffer, &len)| buffer.len().div_ceil(len))
+            .max()
+            .unwrap_or(0);
+        match self.final_record_policy {
+            FinalRecordPolicy::Discard => {}
+            FinalRecordPolicy::PadWithZeros | FinalRecordPolicy::PadWithLastSample => {
+                for (buffer, &len) in self.pending.iter_mut().zip(&self.samples_in_datarecord) {
+                    let value = match self.final_record_policy {
+                        FinalRecordPolicy::PadWithLastSample => {
+                            buffer.last().copied().unwrap_or(0.0)
+                        }
+                        _ => 0.0,
+                    };
+                    buffer.resize(records * len, value);
+                }
+                self.flush_pending()?;
+            }
+        }
+        self.pending.iter_mut().for_each(Vec::clear);
+        Ok(())
+    }
+
+    /// Returns the open file.
+    fn file_mut(&mut self, operation: &'static str) -> Result<&mut dyn WriterBackend> {
+        match &mut self.file {
+            Some(file) => Ok(file.as_mut()),
+            None => Err(EdfError::NotOpen {
+                context: ErrorContext::new(operation, None, self.file_path.clone()),
+            }),
+        }
+    }
+
+    /// Checks that the file is open and no samples buffered by `push` are waiting to be written.
+    fn check_records(&self, operation: &'static str) -> Result<()> {
+        if self.file.is_none() {
+            return Err(EdfError::NotOpen {
+                context: self.context(operation),
+            });
+        }
+        if self.pending.iter().any(|buffer| !buffer.is_empty()) {
+            return Err(EdfError::invalid_argument(
+                self.context(operation),
+                "samples passed to push are still buffered, push the rest of the datarecord first",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of samples of all channels in one datarecord.
+    fn samples_per_datarecord(&self) -> usize {
+        self.samples_in_datarecord.iter().sum()
+    }
+
+    /// Checks that the file is open and `channel_samples` holds one datarecord of every channel.
+    fn check_frame<T>(&self, channel_samples: &[Vec<T>], operation: &'static str) -> Result<()> {
+        self.check_records(operation)?;
+        // 检查通道数量是否匹配
+        if channel_samples.len() != self.header.channels.len() {
+            return Err(EdfError::invalid_argument(
+                self.context(operation),
+                format!(
+                    "给定的通道数据数量({})与header.channels数量({})不一致！",
+                    channel_samples.len(),
+                    self.header.channels.len()
+                ),
+            ));
+        }
+        for (ch_idx, (ch_data, &expected)) in channel_samples
+            .iter()
+            .zip(&self.samples_in_datarecord)
+            .enumerate()
+        {
+            if ch_data.len() != expected {
+                return Err(EdfError::invalid_argument(
+                    ErrorContext::new(operation, Some(ch_idx), self.file_path.clone()),
+                    format!(
+                        "数据点数({})与每个数据记录的采样点数({})不一致",
+                        ch_data.len(),
+                        expected
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Chooses the shortest whole-second datarecord duration in which every channel has a whole
+    /// number of samples and returns it with the number of samples of each channel.
+    /// Uses `header.datarecord_duration` if set.
+    fn datarecord_layout(&self) -> Result<(Duration, Vec<usize>)> {
+        for (i, ch) in self.header.channels.iter().enumerate() {
+            if !(ch.sample_frequency.is_finite() && ch.sample_frequency > 0.0) {
+                return Err(EdfError::invalid_argument(
+                    ErrorContext::new("open", Some(i), self.file_path.clone()),
+                    format!(
+                        "sample_frequency must be positive, got {}",
+                        ch.sample_frequency
+                    ),
+                ));
+            }
+        }
+
+        if let Some(duration) = self.header.datarecord_duration {
+            let micros = duration.as_nanos() % 1_000 == 0;
+            let supported = (micros
+                && duration >= MIN_DATARECORD_DURATION
+                && duration < MICRO_DATARECORD_LIMIT)
+                || (duration.as_nanos() % 10_000 == 0
+                    && duration >= MICRO_DATARECORD_LIMIT
+                    && duration <= Duration::from_secs(MAX_DATARECORD_SECONDS));
+            if !supported {
+                return Err(EdfError::invalid_argument(
+                    self.context("open"),
+                    format!("unsupported datarecord duration {:?}", duration),
+                ));
+            }
+            return match self.samples_for_duration(duration) {
+                Some(samples) => Ok((duration, samples)),
+                None => Err(EdfError::invalid_argument(
+                    self.context("open"),
+                    format!(
+                        "a datarecord of {:?} does not hold a whole number of samples of every channel",
+                        duration
+                    ),
+                )),
+            };
+        }
+
+        for seconds in 1..=MAX_DATARECORD_SECONDS {
+            let duration = Duration::from_secs(seconds);
+            if let Some(samples) = self.samples_for_duration(duration) {
+                return Ok((duration, samples));
+            }
+        }
+        Err(EdfError::invalid_argument(
+            self.context("open"),
+            format!(
+                "no datarecord duration of up to {} s holds a whole number of samples of every channel",
+                MAX_DATARECORD_SECONDS
+            ),
+        ))
+    }
+
+    /// Returns the format to write, checking it against the extension of the path.
+    fn file_format(&self) -> Result<FileFormat> {
+        let ext = self
+            .file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let Some(format) = self.header.file_format else {
+            return Ok(FileFormat::from_extension(ext));
+        };
+
+        let known_ext = ext.eq_ignore_ascii_case("edf") || ext.eq_ignore_ascii_case("bdf");
+        if known_ext && !ext.eq_ignore_ascii_case(format.extension()) {
+            return Err(EdfError::invalid_argument(
+                self.context("open"),
+                format!(
+                    "file format {:?} does not match the extension \".{}\"",
+                    format, ext
+                ),
+            ));
+        }
+        if format.is_plus() {
+            return Ok(format);
+        }
+        let message = if cfg!(not(feature = "pure-rust")) {
+            format!(
+                "edflib can only write EDF+ and BDF+ files, {:?} needs the `pure-rust` feature",
+                format
+            )
+        } else if self.header.annotation_channels.is_some() {
+            format!("{:?} files have no annotation channels", format)
+        } else if self
+            .header
+            .start_datetime
+            .is_some_and(|start| start.subsecond() != 0)
+        {
+            format!("{:?} files cannot store a subsecond start time", format)
+        } else {
+            return Ok(format);
+        };
+        Err(EdfError::invalid_argument(self.context("open"), message))
+    }
+
+    /// Returns the number of samples of each channel in a datarecord of `duration`, if they are whole numbers.
+    fn samples_for_duration(&self, duration: Duration) -> Option<Vec<usize>> {
+        self.header
+            .channels
+            .iter()
+            .map(|ch| {
+                let samples = ch.sample_frequency * duration.as_secs_f64();
+                let rounded = samples.round();
+                // 允许浮点误差，例如 0.1 * 10
+                (rounded >= 1.0 && (samples - rounded).abs() < 1e-6).then_some(rounded as usize)
+            })
+            .collect()
+    }
+
+    fn check_datarecord_len(&self, len: usize, operation: &'static str) -> Result<()> {
+        let samples_per_datarecord = self.samples_per_datarecord();
+        if len == 0 || samples_per_datarecord == 0 || !len.is_multiple_of(samples_per_datarecord) {
+            return Err(EdfError::invalid_argument(
+                self.context(operation),
+                format!(
+                    "数据点数({})不是每个数据记录采样点数({})的整数倍",
+                    len, samples_per_datarecord
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the digital range of whole datarecords of channel-major samples and writes them.
+    fn write_digital_records<T: DigitalSample>(
+        &mut self,
+        samples: &[T],
+        operation: &'static str,
+    ) -> Result<()> {
+        self.check_records(operation)?;
+        self.check_datarecord_len(samples.len(), operation)?;
+        let samples_per_datarecord = self.samples_per_datarecord();
+
+        // 先检查所有采样点，避免写入半个数据记录
+        for record in samples.chunks(samples_per_datarecord) {
+            let mut offset = 0;
+            for (ch_idx, (channel_info, &len)) in self
+                .header
+                .channels
+                .iter()
+                .zip(&self.samples_in_datarecord)
+                .enumerate()
+            {
+                let range = channel_info.digital_min..=channel_info.digital_max;
+                let ch_data = &record[offset..offset + len];
+                offset += len;
+                if let Some(&value) = ch_data
+                    .iter()
+                    .find(|&&value| !range.contains(&value.into()))
+                {
+                    return Err(EdfError::invalid_argument(
+                        ErrorContext::new(operation, Some(ch_idx), self.file_path.clone()),
+                        format!(
+                            "sample {} is outside of the digital range {}..={}",
+                            value.into(),
+                            channel_info.digital_min,
+                            channel_info.digital_max
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let samples: Vec<i32> = samples.iter().map(|&value| value.into()).collect();
+        self.count_datarecords(samples.len(), operation)?;
+        if self.annotation_channels.is_none() {
+            self.held_samples += samples.len();
+            self.held.push(HeldRecords::Digital(samples));
+            return self.limit_held_records(operation);
+        }
+        self.file_mut(operation)?
+            .write_digital_records(&samples, samples_per_datarecord)
+    }
+
+    fn context(&self, operation: &'static str) -> ErrorContext {
+        ErrorContext::new(operation, None, self.file_path.clone())
+    }
+
+    /// Checks the header without creating the file, returning the datarecord duration, the
+    /// samples per datarecord of every channel and the file format.
+    pub(crate) fn check_header(&self) -> Result<(Duration, Vec<usize>, FileFormat)> {
+        self.validate_header()?;
+        let (datarecord_duration, samples_in_datarecord) = self.datarecord_layout()?;
+        Ok((
+            datarecord_duration,
+            samples_in_datarecord,
+            self.file_format()?,
+        ))
+    }
+
+    /// Checks the text fields, the signal ranges and the annotation channel count.
+    fn validate_header(&self) -> Result<()> {
         let patient = &self.header.patient_info;
+        let fields = [
+            ("patient_name", None, &patient.patient_name, MAX_FIELD_LEN),
+            ("patient_code", None, &patient.patient_code, MAX_FIELD_LEN),
+            (
+                "patient_additional",
+                None,
+                &patient.patient_additional,
+                MAX_FIELD_LEN,
+            ),
+            ("admin_code", None, &patient.admin_code, MAX_FIELD_LEN),
+            ("technician", None, &patient.technician, MAX_FIELD_LEN),
+            ("equipment", None, &patient.equipment, MAX_FIELD_LEN),
+            (
+                "recording_additional",
+                None,
+                &patient.recording_additional,
+                MAX_FIELD_LEN,
+            ),
+        ]
+        .into_iter()
+        .chain(self.header.channels.iter().enumerate().flat_map(|(i, ch)| {
+            [
+                ("label", Some(i), &ch.label, MAX_LABEL_LEN),
+                ("transducer", Some(i), &ch.transducer, MAX_FIELD_LEN),
+                (
+                    "physical_dimension",
+                    Some(i),
+                    &ch.physical_dimension,
+                    MAX_PHYSICAL_DIMENSION_LEN,
+                ),
+                ("prefilter", Some(i), &ch.prefilter, MAX_FIELD_LEN),
+            ]
+        }));
 
-        edf.set_equipment(patient.equipment.clone())?;
-        edf.set_patientname(patient.patient_name.clone())?;
-        edf.set_patientcode(patient.patient_code.clone())?;
-        edf.set_sex(patient.sex)?;
-        edf.set_admincode(patient.admin_code.clone())?;
-        edf.set_technician(patient.technician.clone())?;
+        let truncate = self.overlong_text == OverlongText::Truncate;
+        for (name, signal, value, max_len) in fields {
+            if let Err(message) = to_cstring(value, TextEncoding::Latin1, max_len, truncate) {
+                return Err(EdfError::invalid_argument(
+                    ErrorContext::new("open", signal, self.file_path.clone()),
+                    format!("{}: {}", name, message),
+                ));
+            }
+        }
 
+        if self.header.channels.is_empty() {
+            return Err(EdfError::invalid_argument(
+                self.context("open"),
+                "at least one channel is required",
+            ));
+        }
         for (i, ch) in self.header.channels.iter().enumerate() {
-            edf.set_label(i as i32, ch.label.clone())?;
-            edf.set_transducer(i as i32, ch.transducer.clone())?;
-            edf.set_digital_maximum(i as i32, ch.digital_max)?;
-            edf.set_digital_minimum(i as i32, ch.digital_min)?;
-            edf.set_physical_maximum(i as i32, ch.physical_max)?;
-            edf.set_physical_minimum(i as i32, ch.physical_min)?;
-            edf.set_physical_dimension(i as i32, ch.physical_dimension.clone())?;
-            edf.set_samplefrequency(i as i32, ch.sample_frequency)?;
+            let message = if ch.digital_min >= ch.digital_max {
+                format!(
+                    "digital_min {} must be lower than digital_max {}",
+                    ch.digital_min, ch.digital_max
+                )
+            } else if ch.physical_min == ch.physical_max {
+                format!(
+                    "physical_min and physical_max must differ, both are {}",
+                    ch.physical_min
+                )
+            } else {
+                continue;
+            };
+            return Err(EdfError::invalid_argument(
+                ErrorContext::new("open", Some(i), self.file_path.clone()),
+                message,
+            ));
         }
 
+        if let Some(channels) = self.header.annotation_channels {
+            if !(1..=MAX_ANNOTATION_CHANNELS).contains(&channels) {
+                return Err(EdfError::invalid_argument(
+                    self.context("open"),
+                    format!(
+                        "annotation_channels must be between 1 and {}, got {}",
+                        MAX_ANNOTATION_CHANNELS, channels
+                    ),
+                ));
+            }
+        }
         Ok(())
     }
 }
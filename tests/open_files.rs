@@ -0,0 +1,28 @@
+// edflib 的打开文件表是进程全局的，单独的测试程序避免与其他测试并行打开文件
+use edflib::{Edf, EdfError, MAX_OPEN_FILES};
+use tempfile::TempDir;
+
+#[test]
+fn test_max_files_reached() {
+    let dir = TempDir::new().unwrap();
+    let path = |i: usize| dir.path().join(format!("{}.edf", i));
+
+    let files: Vec<Edf> = (0..MAX_OPEN_FILES)
+        .map(|i| Edf::open_file_writeonly(path(i), 1).unwrap())
+        .collect();
+    assert_eq!(Edf::number_of_open_files(), MAX_OPEN_FILES);
+
+    let err = Edf::open_file_writeonly(path(MAX_OPEN_FILES), 1)
+        .err()
+        .unwrap();
+    assert!(matches!(err, EdfError::MaxFilesReached { .. }));
+    assert_eq!(err.path(), path(MAX_OPEN_FILES));
+    assert!(!path(MAX_OPEN_FILES).exists());
+
+    // 关闭文件后可以再次打开
+    drop(files);
+    assert_eq!(Edf::number_of_open_files(), 0);
+    let edf = Edf::open_file_writeonly(path(MAX_OPEN_FILES), 1).unwrap();
+    assert_eq!(Edf::number_of_open_files(), 1);
+    drop(edf);
+}